@@ -0,0 +1,130 @@
+//! Delta throughput on synthetic graphs. Run with `cargo bench`; pass edge
+//! counts to override the default sizes, e.g. `cargo bench -- 5000000`.
+//!
+//! Each size diffs a random graph against a copy with 1% of its edges removed,
+//! 1% added and 0.5% of its nodes renamed. `hash-set` is the previous
+//! implementation (cloning every id into `HashSet`s), kept for comparison.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::time::{Duration, Instant};
+use dot_parser_delta_computator::{
+    compute_delta, GraphData, GraphDelta, GraphLink, GraphNode, IndexedDelta, IndexedGraph, Interner,
+};
+
+const DEFAULT_SIZES: &[usize] = &[100_000, 1_000_000, 2_000_000];
+const RUNS: usize = 3;
+
+/// Small LCG so the bench needs no extra dependencies.
+struct Lcg(u64);
+
+impl Lcg {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 = self.0.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+        ((self.0 >> 33) % n as u64) as usize
+    }
+}
+
+fn node(id: String) -> GraphNode {
+    GraphNode { name: id.clone(), id, attributes: BTreeMap::new(), html_attributes: BTreeSet::new(), cluster: None, metrics: BTreeMap::new(), subgraph: None, display_label: None, colors: BTreeMap::new() }
+}
+
+fn link(source: &str, target: &str) -> GraphLink {
+    GraphLink { source: source.to_string(), target: target.to_string(), label: None, attributes: BTreeMap::new(), html_attributes: BTreeSet::new(), weight: None, display_label: None, colors: BTreeMap::new() }
+}
+
+/// A graph with `edges` random edges over `edges / 5` nodes, and its mutated copy.
+fn synthetic(edges: usize) -> (GraphData, GraphData) {
+    let n = (edges / 5).max(2);
+    let mut rng = Lcg(edges as u64);
+    let ids: Vec<String> = (0..n).map(|i| format!("node_{}", i)).collect();
+    let old = GraphData {
+        nodes: ids.iter().cloned().map(node).collect(),
+        links: (0..edges).map(|_| link(&ids[rng.below(n)], &ids[rng.below(n)])).collect(),
+        ..GraphData::default()
+    };
+
+    let renamed: HashSet<usize> = (0..n / 200).map(|_| rng.below(n)).collect();
+    let name = |i: usize| if renamed.contains(&i) { format!("renamed_{}", i) } else { ids[i].clone() };
+    let index = |id: &str| id["node_".len()..].parse::<usize>().unwrap();
+    let mut new = GraphData {
+        nodes: (0..n).map(|i| node(name(i))).collect(),
+        links: old.links.iter()
+            .filter(|_| rng.below(100) != 0)
+            .map(|l| link(&name(index(&l.source)), &name(index(&l.target))))
+            .collect(),
+        ..GraphData::default()
+    };
+    for _ in 0..edges / 100 {
+        new.links.push(link(&name(rng.below(n)), &name(rng.below(n))));
+    }
+    (old, new)
+}
+
+fn hash_set_delta(g1: &GraphData, g2: &GraphData) -> GraphDelta {
+    let sets = |g: &GraphData| {
+        let nodes: HashSet<String> = g.nodes.iter().map(|n| n.id.clone()).collect();
+        let edges: HashSet<(String, String)> = g.links.iter().map(|e| (e.source.clone(), e.target.clone())).collect();
+        (nodes, edges)
+    };
+    let (nodes1, edges1) = sets(g1);
+    let (nodes2, edges2) = sets(g2);
+    GraphDelta {
+        added_nodes: nodes2.difference(&nodes1).cloned().collect(),
+        removed_nodes: nodes1.difference(&nodes2).cloned().collect(),
+        added_edges: edges2.difference(&edges1).cloned().collect(),
+        removed_edges: edges1.difference(&edges2).cloned().collect(),
+        moved_nodes: Vec::new(),
+        added_clusters: Vec::new(),
+        removed_clusters: Vec::new(),
+    }
+}
+
+/// Fastest of `RUNS` runs, and the value of the last run.
+fn time<T>(mut f: impl FnMut() -> T) -> (Duration, T) {
+    let mut best = Duration::MAX;
+    let mut value = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let v = f();
+        best = best.min(start.elapsed());
+        value = Some(v);
+    }
+    (best, value.unwrap())
+}
+
+fn report(name: &str, edges: usize, elapsed: Duration) {
+    let secs = elapsed.as_secs_f64();
+    println!("  {:<12} {:>9.1} ms {:>9.2} M edges/s", name, secs * 1e3, edges as f64 / secs / 1e6);
+}
+
+fn main() {
+    let sizes: Vec<usize> = std::env::args().skip(1).filter_map(|a| a.parse().ok()).collect();
+    let sizes = if sizes.is_empty() { DEFAULT_SIZES.to_vec() } else { sizes };
+
+    for edges in sizes {
+        let (old, new) = synthetic(edges);
+        let total = old.links.len() + new.links.len();
+        println!("{} + {} edges, {} nodes", old.links.len(), new.links.len(), old.nodes.len());
+
+        let (elapsed, delta) = time(|| compute_delta(&old, &new));
+        report("interned", total, elapsed);
+        let changes = (delta.added_edges.len(), delta.removed_edges.len());
+
+        let (elapsed, _) = time(|| {
+            let mut interner = Interner::with_capacity(old.nodes.len() + new.nodes.len());
+            (IndexedGraph::new(&old, &mut interner), IndexedGraph::new(&new, &mut interner))
+        });
+        report("  index", total, elapsed);
+
+        let mut interner = Interner::with_capacity(old.nodes.len() + new.nodes.len());
+        let (a, b) = (IndexedGraph::new(&old, &mut interner), IndexedGraph::new(&new, &mut interner));
+        let (elapsed, _) = time(|| IndexedDelta::between(&a, &b));
+        report("  merge", total, elapsed);
+
+        let (elapsed, baseline) = time(|| hash_set_delta(&old, &new));
+        report("hash-set", total, elapsed);
+        assert_eq!(changes, (baseline.added_edges.len(), baseline.removed_edges.len()));
+
+        println!("  {} edges added, {} removed", changes.0, changes.1);
+    }
+}
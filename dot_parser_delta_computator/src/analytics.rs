@@ -0,0 +1,476 @@
+//! Structural statistics over a `GraphData`: degrees, components, cycles,
+//! topological order and diameter.
+
+use crate::graph::{Adjacency, GraphData};
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+
+/// Upper bound on the number of cycles reported by `graph_stats`.
+pub const DEFAULT_CYCLE_LIMIT: usize = 100;
+
+#[derive(Serialize, Deserialize)]
+pub struct GraphStats {
+    pub node_count: usize,
+    pub edge_count: usize,
+    /// Maps an in-degree to the number of nodes having it.
+    pub in_degree: BTreeMap<usize, usize>,
+    /// Maps an out-degree to the number of nodes having it.
+    pub out_degree: BTreeMap<usize, usize>,
+    pub connected_components: Vec<Vec<String>>,
+    pub strongly_connected_components: Vec<Vec<String>>,
+    pub cycles: Vec<Vec<String>>,
+    pub is_dag: bool,
+    pub topological_order: Option<Vec<String>>,
+    pub diameter: usize,
+}
+
+fn to_ids(adj: &Adjacency, group: &[usize]) -> Vec<String> {
+    let mut ids: Vec<String> = group.iter().map(|&n| adj.ids[n].to_string()).collect();
+    ids.sort();
+    ids
+}
+
+/// Sort groups of ids by decreasing size, then by their first id.
+fn sort_groups(mut groups: Vec<Vec<String>>) -> Vec<Vec<String>> {
+    groups.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    groups
+}
+
+/// Weakly connected components, i.e. ignoring edge direction.
+pub fn connected_components(graph: &GraphData) -> Vec<Vec<String>> {
+    connected_components_of(&Adjacency::new(graph))
+}
+
+fn connected_components_of(adj: &Adjacency) -> Vec<Vec<String>> {
+    let mut seen = vec![false; adj.len()];
+    let mut groups = Vec::new();
+
+    for start in 0..adj.len() {
+        if seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let mut group = vec![start];
+        let mut stack = vec![start];
+        while let Some(n) = stack.pop() {
+            for m in adj.neighbors(n) {
+                if !seen[m] {
+                    seen[m] = true;
+                    group.push(m);
+                    stack.push(m);
+                }
+            }
+        }
+        groups.push(to_ids(adj, &group));
+    }
+
+    sort_groups(groups)
+}
+
+/// Tarjan's algorithm over `nodes`, following only links between nodes with
+/// `member` set; written iteratively so deep graphs don't overflow the stack.
+fn tarjan(succ: &[Vec<usize>], nodes: &[usize], member: &[bool]) -> Vec<Vec<usize>> {
+    let n = succ.len();
+    let mut index = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = Vec::new();
+    let mut groups = Vec::new();
+    let mut counter = 0;
+
+    for &root in nodes {
+        if index[root] != usize::MAX {
+            continue;
+        }
+        // (node, position in its successor list)
+        let mut work = vec![(root, 0)];
+        while let Some(&mut (v, ref mut pos)) = work.last_mut() {
+            if *pos == 0 {
+                index[v] = counter;
+                low[v] = counter;
+                counter += 1;
+                stack.push(v);
+                on_stack[v] = true;
+            }
+            if let Some(&w) = succ[v].get(*pos) {
+                *pos += 1;
+                if !member[w] {
+                    continue;
+                }
+                if index[w] == usize::MAX {
+                    work.push((w, 0));
+                } else if on_stack[w] {
+                    low[v] = low[v].min(index[w]);
+                }
+                continue;
+            }
+            work.pop();
+            if let Some(&(parent, _)) = work.last() {
+                low[parent] = low[parent].min(low[v]);
+            }
+            if low[v] == index[v] {
+                let mut group = Vec::new();
+                while let Some(w) = stack.pop() {
+                    on_stack[w] = false;
+                    group.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                groups.push(group);
+            }
+        }
+    }
+
+    groups
+}
+
+/// Successors of each node, without duplicate links.
+fn successors(adj: &Adjacency) -> Vec<Vec<usize>> {
+    adj.out.iter()
+        .map(|links| {
+            let mut succ: Vec<usize> = links.iter().map(|&(m, _)| m).collect();
+            succ.sort_unstable();
+            succ.dedup();
+            succ
+        })
+        .collect()
+}
+
+pub(crate) fn scc_indices(adj: &Adjacency) -> Vec<Vec<usize>> {
+    let nodes: Vec<usize> = (0..adj.len()).collect();
+    tarjan(&successors(adj), &nodes, &vec![true; adj.len()])
+}
+
+/// Strongly connected components, including single-node ones.
+pub fn strongly_connected_components(graph: &GraphData) -> Vec<Vec<String>> {
+    strongly_connected_components_of(&Adjacency::new(graph))
+}
+
+fn strongly_connected_components_of(adj: &Adjacency) -> Vec<Vec<String>> {
+    let groups = scc_indices(adj).iter().map(|g| to_ids(adj, g)).collect();
+    sort_groups(groups)
+}
+
+/// Johnson's circuit search: the elementary cycles through `start` within
+/// `member`, appended to `cycles` until it holds `limit` of them. `blocked`
+/// and `blocked_by` must be clear for every member; they are left clear.
+#[allow(clippy::too_many_arguments)]
+fn circuits(
+    succ: &[Vec<usize>],
+    start: usize,
+    member: &[bool],
+    blocked: &mut [bool],
+    blocked_by: &mut [Vec<usize>],
+    touched: &mut Vec<usize>,
+    cycles: &mut Vec<Vec<usize>>,
+    limit: usize,
+) {
+    fn unblock(v: usize, blocked: &mut [bool], blocked_by: &mut [Vec<usize>]) {
+        let mut work = vec![v];
+        while let Some(u) = work.pop() {
+            if blocked[u] {
+                blocked[u] = false;
+                work.append(&mut blocked_by[u]);
+            }
+        }
+    }
+
+    let mut path = vec![start];
+    // (position in the successor list, whether a cycle was found below)
+    let mut work = vec![(0, false)];
+    blocked[start] = true;
+    touched.push(start);
+    while let Some(&mut (ref mut pos, ref mut found)) = work.last_mut() {
+        if cycles.len() >= limit {
+            break;
+        }
+        let v = *path.last().unwrap();
+        if let Some(&w) = succ[v].get(*pos) {
+            *pos += 1;
+            if !member[w] {
+                continue;
+            }
+            if w == start {
+                cycles.push(path.clone());
+                *found = true;
+            } else if !blocked[w] {
+                blocked[w] = true;
+                touched.push(w);
+                path.push(w);
+                work.push((0, false));
+            }
+            continue;
+        }
+        let found = *found;
+        if found {
+            unblock(v, blocked, blocked_by);
+        } else {
+            for &w in succ[v].iter().filter(|&&w| member[w]) {
+                if !blocked_by[w].contains(&v) {
+                    blocked_by[w].push(v);
+                }
+            }
+        }
+        path.pop();
+        work.pop();
+        if let Some(parent) = work.last_mut() {
+            parent.1 |= found;
+        }
+    }
+    for &v in touched.iter() {
+        blocked[v] = false;
+        blocked_by[v].clear();
+    }
+    touched.clear();
+}
+
+/// Elementary cycles, each listed from its smallest node, up to `limit`
+/// cycles. Johnson's algorithm, run only inside strongly connected
+/// components that contain a cycle, so acyclic parts cost a single pass.
+pub fn find_cycles(graph: &GraphData, limit: usize) -> Vec<Vec<String>> {
+    find_cycles_of(&Adjacency::new(graph), limit)
+}
+
+fn find_cycles_of(adj: &Adjacency, limit: usize) -> Vec<Vec<String>> {
+    let n = adj.len();
+    let mut rank = vec![0; n];
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by_key(|&v| adj.ids[v]);
+    for (r, &v) in order.iter().enumerate() {
+        rank[v] = r;
+    }
+    let mut succ = successors(adj);
+    for list in &mut succ {
+        list.sort_by_key(|&w| rank[w]);
+    }
+
+    let mut member = vec![false; n];
+    let mut blocked = vec![false; n];
+    let mut blocked_by = vec![Vec::new(); n];
+    let mut touched = Vec::new();
+    let mut cycles = Vec::new();
+
+    // Components still to search, smallest first node first, so cycles come
+    // out in the order of their smallest node.
+    let mut pending: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    let push_cyclic = |pending: &mut BTreeMap<usize, Vec<usize>>, group: Vec<usize>| {
+        let cyclic = group.len() > 1 || succ[group[0]].contains(&group[0]);
+        if cyclic {
+            let first = group.iter().map(|&v| rank[v]).min().unwrap();
+            pending.insert(first, group);
+        }
+    };
+    for group in tarjan(&succ, &order, &vec![true; n]) {
+        push_cyclic(&mut pending, group);
+    }
+
+    while cycles.len() < limit {
+        let first = match pending.keys().next() {
+            Some(&first) => first,
+            None => break,
+        };
+        let group = pending.remove(&first).unwrap();
+        let start = order[first];
+        for &v in &group {
+            member[v] = true;
+        }
+        circuits(&succ, start, &member, &mut blocked, &mut blocked_by, &mut touched, &mut cycles, limit);
+
+        // Later searches exclude `start`: split the rest of its component.
+        member[start] = false;
+        let rest: Vec<usize> = group.into_iter().filter(|&v| v != start).collect();
+        let parts = tarjan(&succ, &rest, &member);
+        for &v in &rest {
+            member[v] = false;
+        }
+        for part in parts {
+            push_cyclic(&mut pending, part);
+        }
+    }
+
+    cycles.into_iter()
+        .map(|cycle| cycle.into_iter().map(|v| adj.ids[v].to_string()).collect())
+        .collect()
+}
+
+/// Kahn's algorithm, breaking ties by id; `None` if the graph has a cycle.
+pub fn topological_order(graph: &GraphData) -> Option<Vec<String>> {
+    topological_order_of(&Adjacency::new(graph))
+}
+
+fn topological_order_of(adj: &Adjacency) -> Option<Vec<String>> {
+    let mut in_degree: Vec<usize> = adj.inc.iter().map(|l| l.len()).collect();
+    let mut ready: BTreeSet<(&str, usize)> = (0..adj.len())
+        .filter(|&n| in_degree[n] == 0)
+        .map(|n| (adj.ids[n], n))
+        .collect();
+    let mut order = Vec::with_capacity(adj.len());
+
+    while let Some(&first) = ready.iter().next() {
+        ready.remove(&first);
+        let (id, n) = first;
+        order.push(id.to_string());
+        for &(m, _) in &adj.out[n] {
+            in_degree[m] -= 1;
+            if in_degree[m] == 0 {
+                ready.insert((adj.ids[m], m));
+            }
+        }
+    }
+
+    if order.len() == adj.len() {
+        Some(order)
+    } else {
+        None
+    }
+}
+
+/// Whether the graph has no directed cycle.
+pub fn is_dag(graph: &GraphData) -> bool {
+    topological_order(graph).is_some()
+}
+
+/// Longest shortest path, in hops, between two nodes of the same connected
+/// component when edge direction is ignored.
+pub fn diameter(graph: &GraphData) -> usize {
+    diameter_of(&Adjacency::new(graph))
+}
+
+fn diameter_of(adj: &Adjacency) -> usize {
+    let mut best = 0;
+    let mut dist = vec![usize::MAX; adj.len()];
+
+    for start in 0..adj.len() {
+        dist.iter_mut().for_each(|d| *d = usize::MAX);
+        dist[start] = 0;
+        let mut queue = VecDeque::from(vec![start]);
+        while let Some(n) = queue.pop_front() {
+            best = best.max(dist[n]);
+            for m in adj.neighbors(n) {
+                if dist[m] == usize::MAX {
+                    dist[m] = dist[n] + 1;
+                    queue.push_back(m);
+                }
+            }
+        }
+    }
+
+    best
+}
+
+fn histogram<I: Iterator<Item = usize>>(degrees: I) -> BTreeMap<usize, usize> {
+    let mut hist = BTreeMap::new();
+    for d in degrees {
+        *hist.entry(d).or_insert(0) += 1;
+    }
+    hist
+}
+
+/// Compute every statistic of the graph at once
+pub fn graph_stats(graph: &GraphData) -> GraphStats {
+    let adj = Adjacency::new(graph);
+    let topological_order = topological_order_of(&adj);
+
+    GraphStats {
+        node_count: graph.nodes.len(),
+        edge_count: graph.links.len(),
+        in_degree: histogram(adj.inc.iter().map(|l| l.len())),
+        out_degree: histogram(adj.out.iter().map(|l| l.len())),
+        connected_components: connected_components_of(&adj),
+        strongly_connected_components: strongly_connected_components_of(&adj),
+        cycles: find_cycles_of(&adj, DEFAULT_CYCLE_LIMIT),
+        is_dag: topological_order.is_some(),
+        topological_order,
+        diameter: diameter_of(&adj),
+    }
+}
+
+impl fmt::Display for GraphStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hist = |h: &BTreeMap<usize, usize>| {
+            h.iter().map(|(d, c)| format!("{}:{}", d, c)).collect::<Vec<_>>().join(" ")
+        };
+        let nontrivial = self.strongly_connected_components.iter().filter(|c| c.len() > 1);
+
+        writeln!(f, "Nodes:        {}", self.node_count)?;
+        writeln!(f, "Edges:        {}", self.edge_count)?;
+        writeln!(f, "In-degree:    {}", hist(&self.in_degree))?;
+        writeln!(f, "Out-degree:   {}", hist(&self.out_degree))?;
+        writeln!(f, "Components:   {}", self.connected_components.len())?;
+        writeln!(f, "Strong comps: {} ({} non-trivial)",
+            self.strongly_connected_components.len(), nontrivial.count())?;
+        writeln!(f, "Diameter:     {}", self.diameter)?;
+        writeln!(f, "DAG:          {}", if self.is_dag { "yes" } else { "no" })?;
+        if let Some(order) = &self.topological_order {
+            writeln!(f, "Topological:  {}", order.join(" -> "))?;
+        }
+        for cycle in &self.cycles {
+            writeln!(f, "Cycle:        {} -> {}", cycle.join(" -> "), cycle[0])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::parse_graph;
+
+    fn ladder(rungs: usize) -> String {
+        let mut dot = String::from("digraph {\n");
+        for i in 0..rungs {
+            dot.push_str(&format!("n0_{i} -> n1_{i};\n", i = i));
+            if i + 1 < rungs {
+                dot.push_str(&format!("n0_{i} -> n0_{j}; n1_{i} -> n1_{j}; n0_{i} -> n1_{j};\n", i = i, j = i + 1));
+            }
+        }
+        dot.push('}');
+        dot
+    }
+
+    #[test]
+    fn cycles_are_listed_from_their_smallest_node() {
+        let graph = parse_graph("digraph { a -> b; b -> c; c -> a; b -> a; c -> c; d -> e; e -> d; a -> d; x -> y }").unwrap();
+        let cycles = find_cycles(&graph, 10);
+        assert_eq!(cycles, vec![
+            vec!["a", "b"],
+            vec!["a", "b", "c"],
+            vec!["c"],
+            vec!["d", "e"],
+        ]);
+    }
+
+    #[test]
+    fn all_cycles_of_a_complete_digraph_are_found() {
+        let mut dot = String::from("digraph {");
+        for i in 0..6 {
+            for j in (0..6).filter(|&j| j != i) {
+                dot.push_str(&format!("v{} -> v{};", i, j));
+            }
+        }
+        dot.push('}');
+        let graph = parse_graph(&dot).unwrap();
+        // Sum over k = 2..=6 of C(6, k) * (k - 1)!
+        assert_eq!(find_cycles(&graph, usize::MAX).len(), 409);
+        assert_eq!(find_cycles(&graph, 7).len(), 7);
+    }
+
+    #[test]
+    fn large_dags_have_no_cycles() {
+        let graph = parse_graph(&ladder(2000)).unwrap();
+        assert!(find_cycles(&graph, DEFAULT_CYCLE_LIMIT).is_empty());
+        assert!(is_dag(&graph));
+    }
+
+    #[test]
+    fn strongly_connected_components_are_sorted_by_size() {
+        let graph = parse_graph("digraph { a -> b; b -> a; b -> c; c -> d; d -> c; e }").unwrap();
+        assert_eq!(strongly_connected_components(&graph), vec![
+            vec!["a", "b"],
+            vec!["c", "d"],
+            vec!["e"],
+        ]);
+    }
+}
@@ -0,0 +1,194 @@
+//! Keyed pseudonymization of graphs for sharing. Ids, labels and cluster ids
+//! are replaced by HMAC-SHA256 pseudonyms, so the same key always gives the
+//! same names and deltas between anonymized snapshots match the originals;
+//! structure and the chosen attributes are kept.
+
+use crate::color::assign_colors;
+use crate::graph::{unquote, GraphCluster, GraphData, GraphLink, GraphNode};
+use crate::label::decode_labels;
+use serde::{Serialize, Deserialize};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Attributes kept by default: they describe appearance or weight, not names.
+pub const DEFAULT_KEPT_ATTRIBUTES: &[&str] = &[
+    "shape", "style", "color", "fillcolor", "colorscheme", "weight", "penwidth", "len",
+];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AnonymizeOptions {
+    /// Secret key; anyone holding it can confirm guesses of original names.
+    pub key: String,
+    /// Attributes copied unchanged. Other `*label` attributes are
+    /// pseudonymized, everything else is dropped.
+    pub keep_attributes: Vec<String>,
+}
+
+impl AnonymizeOptions {
+    pub fn new(key: &str) -> Self {
+        AnonymizeOptions {
+            key: key.to_string(),
+            keep_attributes: DEFAULT_KEPT_ATTRIBUTES.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+}
+
+pub struct Anonymized {
+    pub graph: GraphData,
+    /// Pseudonym -> original text. Keep it private: it undoes the anonymization.
+    pub reverse: BTreeMap<String, String>,
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+struct Pseudonyms<'a> {
+    key: &'a [u8],
+    reverse: BTreeMap<String, String>,
+}
+
+impl Pseudonyms<'_> {
+    /// `prefix` plus 64 bits of the keyed hash of `kind` and `text`; the
+    /// prefix keeps the result a plain DOT identifier.
+    fn get(&mut self, kind: &str, prefix: &str, text: &str) -> Result<String, String> {
+        let text = unquote(text);
+        let message = format!("{}\0{}", kind, text);
+        let digest = hmac_sha256(self.key, message.as_bytes());
+        let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        let pseudonym = format!("{}{}", prefix, hex);
+        match self.reverse.get(&pseudonym) {
+            Some(original) if *original != text => {
+                Err(format!("Pseudonym collision between {} and {}", original, text))
+            }
+            Some(_) => Ok(pseudonym),
+            None => {
+                self.reverse.insert(pseudonym.clone(), text);
+                Ok(pseudonym)
+            }
+        }
+    }
+
+    fn node(&mut self, id: &str) -> Result<String, String> {
+        self.get("node", "n_", id)
+    }
+
+    fn cluster(&mut self, id: &str) -> Result<String, String> {
+        self.get("cluster", "cluster_", id)
+    }
+
+    fn attributes(&mut self, attributes: &BTreeMap<String, String>, options: &AnonymizeOptions)
+        -> Result<BTreeMap<String, String>, String>
+    {
+        let mut kept = BTreeMap::new();
+        for (k, v) in attributes {
+            if options.keep_attributes.contains(k) {
+                kept.insert(k.clone(), v.clone());
+            } else if k.ends_with("label") {
+                kept.insert(k.clone(), self.get("label", "l_", v)?);
+            }
+        }
+        Ok(kept)
+    }
+}
+
+/// The HTML attributes among those kept as they are; pseudonyms are plain text.
+fn kept_html(html: &BTreeSet<String>, options: &AnonymizeOptions) -> BTreeSet<String> {
+    html.iter().filter(|k| options.keep_attributes.contains(*k)).cloned().collect()
+}
+
+/// Anonymize `graph` with `options.key`. Node and cluster ids and labels get
+/// pseudonyms (`n_…`, `cluster_…`, `l_…`), attributes outside
+/// `keep_attributes` are dropped, and links, clusters, weights, communities
+/// and metrics are kept as they are.
+pub fn anonymize(graph: &GraphData, options: &AnonymizeOptions) -> Result<Anonymized, String> {
+    let mut names = Pseudonyms { key: options.key.as_bytes(), reverse: BTreeMap::new() };
+
+    let mut nodes = Vec::with_capacity(graph.nodes.len());
+    for node in &graph.nodes {
+        let id = names.node(&node.id)?;
+        nodes.push(GraphNode {
+            name: id.clone(),
+            id,
+            attributes: names.attributes(&node.attributes, options)?,
+            html_attributes: kept_html(&node.html_attributes, options),
+            cluster: node.cluster,
+            metrics: node.metrics.clone(),
+            subgraph: node.subgraph.as_deref().map(|c| names.cluster(c)).transpose()?,
+            display_label: None,
+            colors: BTreeMap::new(),
+        });
+    }
+    // Pseudonyms don't sort like the originals.
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut links = Vec::with_capacity(graph.links.len());
+    for link in &graph.links {
+        let attributes = names.attributes(&link.attributes, options)?;
+        links.push(GraphLink {
+            source: names.node(&link.source)?,
+            target: names.node(&link.target)?,
+            label: attributes.get("label").cloned(),
+            attributes,
+            html_attributes: kept_html(&link.html_attributes, options),
+            weight: link.weight,
+            display_label: None,
+            colors: BTreeMap::new(),
+        });
+    }
+
+    let mut clusters = Vec::with_capacity(graph.clusters.len());
+    for cluster in &graph.clusters {
+        clusters.push(GraphCluster {
+            id: names.cluster(&cluster.id)?,
+            parent: cluster.parent.as_deref().map(|p| names.cluster(p)).transpose()?,
+            attributes: names.attributes(&cluster.attributes, options)?,
+            html_attributes: kept_html(&cluster.html_attributes, options),
+        });
+    }
+
+    let mut anonymized = GraphData {
+        nodes,
+        links,
+        clusters,
+        directed: graph.directed,
+        attributes: names.attributes(&graph.attributes, options)?,
+        html_attributes: kept_html(&graph.html_attributes, options),
+    };
+    decode_labels(&mut anonymized, None);
+    assign_colors(&mut anonymized);
+    Ok(Anonymized { graph: anonymized, reverse: names.reverse })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::parse_graph;
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        let hex: String = hmac_sha256(b"Jefe", b"what do ya want for nothing?").iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn pseudonyms_depend_only_on_the_key() {
+        let graph = parse_graph("digraph { subgraph cluster_x { a [label=secret, shape=box] } a -> b }").unwrap();
+        let first = anonymize(&graph, &AnonymizeOptions::new("k1")).unwrap();
+        let again = anonymize(&graph, &AnonymizeOptions::new("k1")).unwrap();
+        let other = anonymize(&graph, &AnonymizeOptions::new("k2")).unwrap();
+        let ids = |a: &Anonymized| a.graph.nodes.iter().map(|n| n.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&first), ids(&again));
+        assert_ne!(ids(&first), ids(&other));
+        assert!(ids(&first).iter().all(|id| id.starts_with("n_") && id.len() == 18));
+
+        let node = first.graph.nodes.iter().find(|n| first.reverse[&n.id] == "a").unwrap();
+        assert_eq!(node.attributes["shape"], "box");
+        assert!(node.attributes["label"].starts_with("l_"));
+        assert_eq!(first.reverse[&node.attributes["label"]], "secret");
+        assert!(node.subgraph.as_deref().unwrap().starts_with("cluster_"));
+    }
+}
@@ -0,0 +1,162 @@
+//! Canonical form of a `GraphData`, so graphs that differ only in statement
+//! order, whitespace or quoting compare (and hash) equal.
+
+use crate::graph::{unquote, write_dot, GraphCluster, GraphData, GraphLink, GraphNode};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// 64-bit FNV-1a; stable across platforms and releases, unlike `DefaultHasher`.
+pub(crate) fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Graphviz attributes holding a single number; only their values are
+/// compared as numbers, so `label="007"` and `label="7"` stay different.
+const NUMERIC_ATTRIBUTES: &[&str] = &[
+    "arrowsize", "fontsize", "height", "labelangle", "labeldistance", "labelfontsize", "len",
+    "minlen", "nodesep", "penwidth", "peripheries", "ranksep", "sides", "weight", "width",
+];
+
+/// Trim an attribute value and, for numeric attributes, write numbers in
+/// their shortest form (`1.0` and `1` both become `1`, `.5` becomes `0.5`).
+fn normalize_value(key: &str, value: &str) -> String {
+    let value = value.trim();
+    if !NUMERIC_ATTRIBUTES.contains(&key) {
+        return value.to_string();
+    }
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() && !value.contains(|c: char| c.is_ascii_alphabetic()) => {
+            number.to_string()
+        }
+        _ => value.to_string(),
+    }
+}
+
+fn normalize_attributes(attributes: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    attributes.iter()
+        .map(|(k, v)| {
+            let key = unquote(k.trim());
+            let value = normalize_value(&key, v);
+            (key, value)
+        })
+        .collect()
+}
+
+fn normalize_keys(keys: &BTreeSet<String>) -> BTreeSet<String> {
+    keys.iter().map(|k| unquote(k.trim())).collect()
+}
+
+/// Canonical copy of `graph`: ids without redundant quotes (merging nodes
+/// declared both quoted and unquoted), trimmed and normalized attribute
+/// values, nodes sorted by id, links by endpoints, then attributes, and DOT
+/// clusters by id. Directedness and root graph attributes are kept.
+/// Communities, metrics, decoded labels and resolved colors are dropped, as
+/// they are derived.
+pub fn canonicalize(graph: &GraphData) -> GraphData {
+    let mut nodes: Vec<GraphNode> = graph.nodes.iter()
+        .map(|node| {
+            let id = unquote(&node.id);
+            GraphNode {
+                name: id.clone(),
+                id,
+                attributes: normalize_attributes(&node.attributes),
+                html_attributes: normalize_keys(&node.html_attributes),
+                cluster: None,
+                metrics: BTreeMap::new(),
+                subgraph: node.subgraph.as_deref().map(unquote),
+                display_label: None,
+                colors: BTreeMap::new(),
+            }
+        })
+        .collect();
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    // `A` and `"A"` are the same node once unquoted.
+    nodes.dedup_by(|later, first| {
+        let same = later.id == first.id;
+        if same {
+            first.attributes.append(&mut later.attributes);
+            first.html_attributes.append(&mut later.html_attributes);
+        }
+        same
+    });
+
+    let mut links: Vec<GraphLink> = graph.links.iter()
+        .map(|link| {
+            let attributes = normalize_attributes(&link.attributes);
+            GraphLink {
+                source: unquote(&link.source),
+                target: unquote(&link.target),
+                label: attributes.get("label").cloned(),
+                attributes,
+                html_attributes: normalize_keys(&link.html_attributes),
+                weight: link.weight,
+                display_label: None,
+                colors: BTreeMap::new(),
+            }
+        })
+        .collect();
+    links.sort_by(|a, b| {
+        (&a.source, &a.target, &a.attributes).cmp(&(&b.source, &b.target, &b.attributes))
+    });
+
+    let mut clusters: Vec<GraphCluster> = graph.clusters.iter()
+        .map(|cluster| GraphCluster {
+            id: unquote(&cluster.id),
+            parent: cluster.parent.as_deref().map(unquote),
+            attributes: normalize_attributes(&cluster.attributes),
+            html_attributes: normalize_keys(&cluster.html_attributes),
+        })
+        .collect();
+    clusters.sort_by(|a, b| a.id.cmp(&b.id));
+    clusters.dedup_by(|later, first| later.id == first.id);
+
+    GraphData {
+        nodes,
+        links,
+        clusters,
+        directed: graph.directed,
+        attributes: normalize_attributes(&graph.attributes),
+        html_attributes: normalize_keys(&graph.html_attributes),
+    }
+}
+
+/// Stable content hash of the canonical form, as 16 hex digits.
+pub fn fingerprint(graph: &GraphData) -> String {
+    format!("{:016x}", fnv1a(&write_dot(&canonicalize(graph))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::parse_graph;
+
+    fn fingerprint_of(dot: &str) -> String {
+        fingerprint(&parse_graph(dot).unwrap())
+    }
+
+    #[test]
+    fn reordered_and_requoted_graphs_share_a_fingerprint() {
+        let a = fingerprint_of("digraph { a -> b [penwidth=1.0]; \"b\" -> c; c [label=\"x\"] }");
+        let b = fingerprint_of("digraph {\n  c [label=x]\n  b -> c\n  \"a\" -> b [penwidth=\"1\"]\n}");
+        assert_eq!(a, b);
+        assert_ne!(a, fingerprint_of("digraph { a -> b; b -> c; c [label=x] }"));
+    }
+
+    #[test]
+    fn labels_compare_as_text() {
+        assert_ne!(fingerprint_of("digraph { a [label=\"007\"] }"), fingerprint_of("digraph { a [label=\"7\"] }"));
+        assert_eq!(fingerprint_of("digraph { a -> b [weight=.5] }"), fingerprint_of("digraph { a -> b [weight=0.50] }"));
+    }
+
+    #[test]
+    fn directedness_and_graph_attributes_count() {
+        let undirected = "graph { rankdir=LR; a -- b; b -- c [label=\"x\"] }";
+        assert_ne!(fingerprint_of(undirected), fingerprint_of("digraph { a -> b; b -> c [label=\"x\"] }"));
+        assert_ne!(fingerprint_of(undirected), fingerprint_of("graph { a -- b; b -- c [label=\"x\"] }"));
+        assert_eq!(fingerprint_of(undirected), fingerprint_of("graph { b -- c [label=x]; a -- b; rankdir=\"LR\" }"));
+
+        let canonical = canonicalize(&parse_graph(undirected).unwrap());
+        assert!(!canonical.directed);
+        assert_eq!(canonical.attributes["rankdir"], "LR");
+        assert_eq!(write_dot(&canonical), "graph {\n    rankdir=LR;\n    a;\n    b;\n    c;\n    a -- b;\n    b -- c [label=x];\n}\n");
+    }
+}
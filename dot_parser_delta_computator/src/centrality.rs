@@ -0,0 +1,236 @@
+//! Node centrality metrics. Each function returns one score per node, in the
+//! order of `GraphData::nodes`.
+
+use crate::graph::{Adjacency, GraphData};
+use std::collections::VecDeque;
+
+const MAX_ITERATIONS: usize = 100;
+const TOLERANCE: f64 = 1e-10;
+
+/// PageRank with the given damping factor; rank of dangling nodes is spread evenly.
+pub fn pagerank(graph: &GraphData, damping: f64) -> Vec<f64> {
+    let adj = Adjacency::new(graph);
+    let n = adj.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let mut rank = vec![1.0 / n as f64; n];
+
+    for _ in 0..MAX_ITERATIONS {
+        let dangling: f64 = (0..n).filter(|&v| adj.out[v].is_empty()).map(|v| rank[v]).sum();
+        let base = (1.0 - damping) / n as f64 + damping * dangling / n as f64;
+        let mut next = vec![base; n];
+        for (v, out) in adj.out.iter().enumerate() {
+            let share = damping * rank[v] / out.len().max(1) as f64;
+            for &(w, _) in out {
+                next[w] += share;
+            }
+        }
+        let delta: f64 = next.iter().zip(&rank).map(|(a, b)| (a - b).abs()).sum();
+        rank = next;
+        if delta < TOLERANCE {
+            break;
+        }
+    }
+    rank
+}
+
+/// Breadth-first distances from `source` along edge direction.
+fn distances(adj: &Adjacency, source: usize) -> Vec<usize> {
+    let mut dist = vec![usize::MAX; adj.len()];
+    let mut queue = VecDeque::new();
+    dist[source] = 0;
+    queue.push_back(source);
+    while let Some(v) = queue.pop_front() {
+        for &(w, _) in &adj.out[v] {
+            if dist[w] == usize::MAX {
+                dist[w] = dist[v] + 1;
+                queue.push_back(w);
+            }
+        }
+    }
+    dist
+}
+
+/// Betweenness (Brandes), normalized by `(n - 1)(n - 2)`.
+pub fn betweenness(graph: &GraphData) -> Vec<f64> {
+    let adj = Adjacency::new(graph);
+    let n = adj.len();
+    let mut centrality = vec![0.0; n];
+    // Successors once each, so parallel links don't count as more shortest
+    // paths and predecessor lists need no duplicate check.
+    let mut last = vec![usize::MAX; n];
+    let succ: Vec<Vec<usize>> = adj.out.iter().enumerate()
+        .map(|(v, out)| out.iter().map(|&(w, _)| w).filter(|&w| std::mem::replace(&mut last[w], v) != v).collect())
+        .collect();
+
+    for s in 0..n {
+        let mut stack = Vec::with_capacity(n);
+        let mut preds: Vec<Vec<usize>> = vec![Vec::new(); n];
+        let mut sigma = vec![0.0; n];
+        let mut dist = vec![usize::MAX; n];
+        let mut queue = VecDeque::new();
+        sigma[s] = 1.0;
+        dist[s] = 0;
+        queue.push_back(s);
+
+        while let Some(v) = queue.pop_front() {
+            stack.push(v);
+            for &w in &succ[v] {
+                if dist[w] == usize::MAX {
+                    dist[w] = dist[v] + 1;
+                    queue.push_back(w);
+                }
+                if dist[w] == dist[v] + 1 {
+                    sigma[w] += sigma[v];
+                    preds[w].push(v);
+                }
+            }
+        }
+
+        let mut delta = vec![0.0; n];
+        while let Some(w) = stack.pop() {
+            for &v in &preds[w] {
+                delta[v] += sigma[v] / sigma[w] * (1.0 + delta[w]);
+            }
+            if w != s {
+                centrality[w] += delta[w];
+            }
+        }
+    }
+
+    if n > 2 {
+        let scale = ((n - 1) * (n - 2)) as f64;
+        centrality.iter_mut().for_each(|c| *c /= scale);
+    }
+    centrality
+}
+
+/// Closeness along outgoing edges, scaled by the reachable fraction of the
+/// graph (Wasserman-Faust) so disconnected graphs are handled.
+pub fn closeness(graph: &GraphData) -> Vec<f64> {
+    let adj = Adjacency::new(graph);
+    let n = adj.len();
+
+    (0..n)
+        .map(|v| {
+            let dist = distances(&adj, v);
+            let reached = dist.iter().filter(|&&d| d != usize::MAX && d > 0);
+            let (count, total) = reached.fold((0usize, 0usize), |(c, t), &d| (c + 1, t + d));
+            if total == 0 {
+                0.0
+            } else {
+                (count as f64 / (n - 1) as f64) * (count as f64 / total as f64)
+            }
+        })
+        .collect()
+}
+
+/// Eigenvector centrality of the undirected graph, by power iteration on
+/// `A + I` (the shift keeps bipartite graphs from oscillating).
+pub fn eigenvector(graph: &GraphData) -> Vec<f64> {
+    let adj = Adjacency::new(graph);
+    let n = adj.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let mut score = vec![1.0 / (n as f64).sqrt(); n];
+
+    for _ in 0..MAX_ITERATIONS {
+        let mut next = score.clone();
+        for (v, s) in next.iter_mut().enumerate() {
+            *s += adj.neighbors(v).map(|w| score[w]).sum::<f64>();
+        }
+        let norm = next.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            break;
+        }
+        next.iter_mut().for_each(|x| *x /= norm);
+        let delta: f64 = next.iter().zip(&score).map(|(a, b)| (a - b).abs()).sum();
+        score = next;
+        if delta < TOLERANCE {
+            break;
+        }
+    }
+    score
+}
+
+/// Compute every centrality and store it in `GraphNode::metrics` under
+/// `pagerank`, `betweenness`, `closeness` and `eigenvector`.
+pub fn compute_centrality(graph: &mut GraphData) {
+    let scores = [
+        ("pagerank", pagerank(graph, 0.85)),
+        ("betweenness", betweenness(graph)),
+        ("closeness", closeness(graph)),
+        ("eigenvector", eigenvector(graph)),
+    ];
+    for (name, values) in scores.iter() {
+        for (node, value) in graph.nodes.iter_mut().zip(values) {
+            node.metrics.insert(name.to_string(), *value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::parse_graph;
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    /// Hub `c` linked both ways to leaves `x`, `y` and `z`.
+    fn star() -> GraphData {
+        parse_graph("digraph { c -> x; x -> c; c -> y; y -> c; c -> z; z -> c }").unwrap()
+    }
+
+    /// `a -> b -> c`.
+    fn path() -> GraphData {
+        parse_graph("digraph { a -> b -> c }").unwrap()
+    }
+
+    #[test]
+    fn pagerank_of_star_and_path() {
+        // Hub: r = 0.15/4 + 0.85 * 3 * leaf, leaf: r = 0.15/4 + 0.85 * hub / 3.
+        let hub = 0.133125 / 0.2775;
+        let leaf = (1.0 - hub) / 3.0;
+        assert_close(&pagerank(&star(), 0.85), &[hub, leaf, leaf, leaf]);
+        // `c` dangles, so every node gets b = 0.05 + 0.85 * c / 3 on top of
+        // its in-links: a = b, b = 1.85b, c = 2.5725b, summing to 1.
+        let base = 1.0 / 5.4225;
+        assert_close(&pagerank(&path(), 0.85), &[base, 1.85 * base, 2.5725 * base]);
+    }
+
+    #[test]
+    fn betweenness_of_star_and_path() {
+        // The hub is on all 6 leaf-to-leaf paths, out of (n - 1)(n - 2) = 6.
+        assert_close(&betweenness(&star()), &[1.0, 0.0, 0.0, 0.0]);
+        // `b` is on the one path `a -> c`, out of 2.
+        assert_close(&betweenness(&path()), &[0.0, 0.5, 0.0]);
+        // Parallel links are not extra shortest paths.
+        let doubled = parse_graph("digraph { a -> b; a -> b; b -> c; a -> d -> c }").unwrap();
+        assert_close(&betweenness(&doubled), &[0.0, 1.0 / 12.0, 0.0, 1.0 / 12.0]);
+    }
+
+    #[test]
+    fn closeness_of_star_and_path() {
+        // A leaf reaches the hub in 1 and the other leaves in 2: 3 / 5.
+        assert_close(&closeness(&star()), &[1.0, 0.6, 0.6, 0.6]);
+        // `a` reaches both others at distance 3 in total; `b` half the graph at 1.
+        assert_close(&closeness(&path()), &[2.0 / 3.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn eigenvector_of_star_and_path() {
+        // Principal eigenvector of a 3-leaf star: (sqrt(3), 1, 1, 1) / sqrt(6).
+        let star = parse_graph("digraph { c -> x; c -> y; c -> z }").unwrap();
+        let leaf = 1.0 / 6f64.sqrt();
+        assert_close(&eigenvector(&star), &[3f64.sqrt() * leaf, leaf, leaf, leaf]);
+        // And of a 3-node path: (1, sqrt(2), 1) / 2.
+        assert_close(&eigenvector(&path()), &[0.5, 0.5f64.sqrt(), 0.5]);
+    }
+}
@@ -0,0 +1,358 @@
+//! Louvain community detection over a `GraphData`, treating links as
+//! undirected and weighted by their `weight` (1 when absent).
+
+use crate::graph::{Adjacency, GraphData};
+use crate::rng::SplitMix64;
+use serde::{Serialize, Deserialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LouvainOptions {
+    /// Seed for the node visiting order; equal seeds give equal clusterings.
+    pub seed: u64,
+    /// Modularity resolution; higher values favour smaller communities.
+    pub resolution: f64,
+    /// Communities smaller than this are merged into their most connected neighbour.
+    pub min_cluster_size: usize,
+    /// Maximum number of local-moving sweeps per level.
+    pub max_sweeps: usize,
+}
+
+impl Default for LouvainOptions {
+    fn default() -> Self {
+        LouvainOptions { seed: 0, resolution: 1.0, min_cluster_size: 1, max_sweeps: 32 }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Communities {
+    pub count: usize,
+    pub modularity: f64,
+}
+
+/// One level of the Louvain hierarchy: symmetric weighted adjacency plus
+/// the weight of the loops of each node (counted twice, like degrees).
+struct Level {
+    adj: Vec<Vec<(usize, f64)>>,
+    loops: Vec<f64>,
+}
+
+impl Level {
+    fn degree(&self, n: usize) -> f64 {
+        self.adj[n].iter().map(|&(_, w)| w).sum::<f64>() + self.loops[n]
+    }
+
+    /// Move nodes between communities while modularity improves.
+    fn local_moving(&self, options: &LouvainOptions, rng: &mut SplitMix64) -> Option<Vec<usize>> {
+        let n = self.adj.len();
+        let degree: Vec<f64> = (0..n).map(|i| self.degree(i)).collect();
+        let m2: f64 = degree.iter().sum();
+        if m2 == 0.0 {
+            return None;
+        }
+
+        let mut community: Vec<usize> = (0..n).collect();
+        let mut tot = degree.clone();
+        let mut order: Vec<usize> = (0..n).collect();
+        let mut weight_to = vec![0.0; n];
+        let mut moved = false;
+
+        for _ in 0..options.max_sweeps {
+            rng.shuffle(&mut order);
+            let mut improved = false;
+
+            for &i in &order {
+                let current = community[i];
+                tot[current] -= degree[i];
+
+                let mut candidates = vec![current];
+                for &(j, w) in &self.adj[i] {
+                    let c = community[j];
+                    if weight_to[c] == 0.0 && c != current {
+                        candidates.push(c);
+                    }
+                    weight_to[c] += w;
+                }
+
+                let gain = |c: usize| weight_to[c] - options.resolution * tot[c] * degree[i] / m2;
+                let mut best = current;
+                let mut best_gain = gain(current);
+                for &c in &candidates[1..] {
+                    let g = gain(c);
+                    if g > best_gain + 1e-12 {
+                        best = c;
+                        best_gain = g;
+                    }
+                }
+
+                for &c in &candidates {
+                    weight_to[c] = 0.0;
+                }
+                tot[best] += degree[i];
+                if best != current {
+                    community[i] = best;
+                    improved = true;
+                    moved = true;
+                }
+            }
+
+            if !improved {
+                break;
+            }
+        }
+
+        if moved {
+            Some(community)
+        } else {
+            None
+        }
+    }
+
+    /// Collapse each community into a single node.
+    fn aggregate(&self, community: &[usize], count: usize) -> Level {
+        let mut adj: Vec<Vec<(usize, f64)>> = vec![Vec::new(); count];
+        let mut loops = vec![0.0; count];
+
+        for (i, neighbors) in self.adj.iter().enumerate() {
+            let ci = community[i];
+            loops[ci] += self.loops[i];
+            for &(j, w) in neighbors {
+                let cj = community[j];
+                if ci == cj {
+                    loops[ci] += w;
+                } else if let Some(entry) = adj[ci].iter_mut().find(|(c, _)| *c == cj) {
+                    entry.1 += w;
+                } else {
+                    adj[ci].push((cj, w));
+                }
+            }
+        }
+
+        Level { adj, loops }
+    }
+}
+
+/// Renumber community labels to `0..count`, in order of first appearance.
+fn renumber(community: &mut [usize]) -> usize {
+    let mut map = vec![usize::MAX; community.len()];
+    let mut count = 0;
+    for c in community.iter_mut() {
+        if map[*c] == usize::MAX {
+            map[*c] = count;
+            count += 1;
+        }
+        *c = map[*c];
+    }
+    count
+}
+
+fn link_weight(graph: &GraphData, l: usize) -> f64 {
+    graph.links[l].weight
+        .filter(|w| *w > 0.0 && w.is_finite())
+        .unwrap_or(1.0)
+}
+
+/// Build the first level, with nodes ranked by id so the result doesn't
+/// depend on the order of `graph.nodes`.
+fn base_level(graph: &GraphData, adj: &Adjacency, rank: &[usize]) -> Level {
+    let n = adj.len();
+    let mut level = Level { adj: vec![Vec::new(); n], loops: vec![0.0; n] };
+
+    for (s, out) in adj.out.iter().enumerate() {
+        for &(t, l) in out {
+            let w = link_weight(graph, l);
+            let (a, b) = (rank[s], rank[t]);
+            if a == b {
+                level.loops[a] += 2.0 * w;
+            } else {
+                level.adj[a].push((b, w));
+                level.adj[b].push((a, w));
+            }
+        }
+    }
+    level
+}
+
+/// Merge communities smaller than `min_size` into the neighbouring community
+/// they are most strongly connected to.
+fn merge_small(level: &Level, membership: &mut [usize], count: usize, min_size: usize) {
+    let mut size = vec![0; count];
+    for &c in membership.iter() {
+        size[c] += 1;
+    }
+
+    for small in 0..count {
+        if size[small] == 0 || size[small] >= min_size {
+            continue;
+        }
+        let mut weight_to = vec![0.0; count];
+        for (i, neighbors) in level.adj.iter().enumerate() {
+            if membership[i] != small {
+                continue;
+            }
+            for &(j, w) in neighbors {
+                weight_to[membership[j]] += w;
+            }
+        }
+        let target = (0..count)
+            .filter(|&c| c != small && size[c] >= min_size && weight_to[c] > 0.0)
+            .fold(None, |best: Option<usize>, c| match best {
+                Some(b) if weight_to[b] >= weight_to[c] => Some(b),
+                _ => Some(c),
+            });
+        if let Some(target) = target {
+            for c in membership.iter_mut().filter(|c| **c == small) {
+                *c = target;
+            }
+            size[target] += size[small];
+            size[small] = 0;
+        }
+    }
+}
+
+fn modularity(level: &Level, membership: &[usize], count: usize, resolution: f64) -> f64 {
+    let mut inside = vec![0.0; count];
+    let mut tot = vec![0.0; count];
+    for (i, neighbors) in level.adj.iter().enumerate() {
+        let c = membership[i];
+        tot[c] += level.degree(i);
+        inside[c] += level.loops[i];
+        for &(j, w) in neighbors {
+            if membership[j] == c {
+                inside[c] += w;
+            }
+        }
+    }
+    let m2: f64 = tot.iter().sum();
+    if m2 == 0.0 {
+        return 0.0;
+    }
+    (0..count).map(|c| inside[c] / m2 - resolution * (tot[c] / m2).powi(2)).sum()
+}
+
+/// Run Louvain and write the community of each node into `GraphNode::cluster`.
+/// Communities are numbered by decreasing size.
+pub fn detect_communities(graph: &mut GraphData, options: &LouvainOptions) -> Communities {
+    let (base, rank) = {
+        let adj = Adjacency::new(graph);
+        let mut order: Vec<usize> = (0..adj.len()).collect();
+        order.sort_by_key(|&n| adj.ids[n]);
+        let mut rank = vec![0; adj.len()];
+        for (r, &n) in order.iter().enumerate() {
+            rank[n] = r;
+        }
+        (base_level(graph, &adj, &rank), rank)
+    };
+
+    let mut rng = SplitMix64::new(options.seed);
+    let mut membership: Vec<usize> = (0..base.adj.len()).collect();
+    let mut level_owned: Option<Level> = None;
+
+    loop {
+        let level = level_owned.as_ref().unwrap_or(&base);
+        let mut community = match level.local_moving(options, &mut rng) {
+            Some(community) => community,
+            None => break,
+        };
+        let count = renumber(&mut community);
+        for c in membership.iter_mut() {
+            *c = community[*c];
+        }
+        if count == level.adj.len() {
+            break;
+        }
+        level_owned = Some(level.aggregate(&community, count));
+    }
+
+    let mut count = renumber(&mut membership);
+    if options.min_cluster_size > 1 {
+        merge_small(&base, &mut membership, count, options.min_cluster_size);
+        count = renumber(&mut membership);
+    }
+
+    // Number communities by decreasing size, ties by first member.
+    let mut size = vec![0; count];
+    for &c in &membership {
+        size[c] += 1;
+    }
+    let mut by_size: Vec<usize> = (0..count).collect();
+    by_size.sort_by(|&a, &b| size[b].cmp(&size[a]).then(a.cmp(&b)));
+    let mut relabel = vec![0; count];
+    for (new, &old) in by_size.iter().enumerate() {
+        relabel[old] = new;
+    }
+    for c in membership.iter_mut() {
+        *c = relabel[*c];
+    }
+
+    let modularity = modularity(&base, &membership, count, options.resolution);
+    for (node, &r) in graph.nodes.iter_mut().zip(&rank) {
+        node.cluster = Some(membership[r]);
+    }
+
+    Communities { count, modularity }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::parse_graph;
+
+    fn clusters(graph: &GraphData) -> Vec<(String, usize)> {
+        graph.nodes.iter().map(|n| (n.id.clone(), n.cluster.unwrap())).collect()
+    }
+
+    /// `rings` rings of `size` nodes, each linked to the next by one edge.
+    fn rings(rings: usize, size: usize) -> GraphData {
+        let mut dot = String::from("graph {\n");
+        for r in 0..rings {
+            for i in 0..size {
+                dot.push_str(&format!("r{r}_{i} -- r{r}_{j}; r{r}_{i} -- r{r}_{k};\n", r = r, i = i, j = (i + 1) % size, k = (i + 2) % size));
+            }
+            dot.push_str(&format!("r{}_0 -- r{}_0;\n", r, (r + 1) % rings));
+        }
+        dot.push('}');
+        parse_graph(&dot).unwrap()
+    }
+
+    #[test]
+    fn equal_seeds_give_equal_partitions() {
+        let options = LouvainOptions { seed: 7, ..LouvainOptions::default() };
+        let (mut first, mut again) = (rings(12, 6), rings(12, 6));
+        let a = detect_communities(&mut first, &options);
+        let b = detect_communities(&mut again, &options);
+        assert_eq!(clusters(&first), clusters(&again));
+        assert_eq!((a.count, a.modularity), (b.count, b.modularity));
+        assert!(a.count > 1);
+    }
+
+    #[test]
+    fn bridged_cliques_split_in_two() {
+        let mut graph = parse_graph("graph {
+            a -- b; a -- c; a -- d; b -- c; b -- d; c -- d;
+            w -- x; w -- y; w -- z; x -- y; x -- z; y -- z;
+            d -- w
+        }").unwrap();
+        let communities = detect_communities(&mut graph, &LouvainOptions::default());
+        assert_eq!(communities.count, 2);
+        let of = |id: &str| graph.nodes.iter().find(|n| n.id == id).unwrap().cluster.unwrap();
+        assert!(["b", "c", "d"].iter().all(|&id| of(id) == of("a")));
+        assert!(["x", "y", "z"].iter().all(|&id| of(id) == of("w")));
+        assert_ne!(of("a"), of("w"));
+    }
+
+    #[test]
+    fn modularity_of_two_bridged_triangles() {
+        let mut graph = parse_graph("graph { a -- b -- c -- a; x -- y -- z -- x; c -- x }").unwrap();
+        let communities = detect_communities(&mut graph, &LouvainOptions::default());
+        // Each triangle: 3 of the 7 edges inside, degree sum 7 of 14:
+        // 2 * (6/14 - (7/14)^2) = 5/14.
+        assert_eq!(communities.count, 2);
+        assert!((communities.modularity - 5.0 / 14.0).abs() < 1e-12, "{}", communities.modularity);
+
+        // One edge: apart, each end has no inside weight and half the
+        // degree, so -2 * (1/2)^2; together, 2/2 - 1^2.
+        let level = Level { adj: vec![vec![(1, 1.0)], vec![(0, 1.0)]], loops: vec![0.0; 2] };
+        assert_eq!(modularity(&level, &[0, 1], 2, 1.0), -0.5);
+        assert_eq!(modularity(&level, &[0, 0], 1, 1.0), 0.0);
+    }
+}
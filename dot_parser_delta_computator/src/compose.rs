@@ -0,0 +1,243 @@
+//! Merge graphs from several DOT files into one.
+//!
+//! Each part can namespace its ids with a prefix (`chat::Router`); nodes get
+//! an `origin` attribute naming their file. Links between files come from a
+//! JSON mapping, e.g.
+//!
+//! ```json
+//! {
+//!   "prefixes": { "chat_server.dot": "chat" },
+//!   "links": [{ "from": "chat::MessageRouter", "to": "store::Database",
+//!               "attributes": { "label": "persists" } }]
+//! }
+//! ```
+
+use crate::color::resolve_colors;
+use crate::graph::{unquote, Adjacency, GraphCluster, GraphData, GraphLink, GraphNode};
+use crate::label::{link_label, node_label};
+use crate::weight::{link_weight, WeightOptions};
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Node attribute holding the file a composed node came from.
+pub const ORIGIN_ATTRIBUTE: &str = "origin";
+
+/// One input of `compose`.
+pub struct ComposePart {
+    /// File name, recorded in each node's `origin` attribute.
+    pub origin: String,
+    /// Namespace for the part's node and cluster ids; without one, nodes with
+    /// the same id in several parts are merged.
+    pub prefix: Option<String>,
+    pub graph: GraphData,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CrossLink {
+    /// Composed node ids (with prefixes), quoted or not.
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ComposeMapping {
+    /// File name -> id prefix.
+    #[serde(default)]
+    pub prefixes: BTreeMap<String, String>,
+    #[serde(default)]
+    pub links: Vec<CrossLink>,
+}
+
+/// Parse a mapping file
+pub fn parse_mapping(text: &str) -> Result<ComposeMapping, String> {
+    serde_json::from_str(text).map_err(|e| format!("Invalid mapping: {}", e))
+}
+
+/// `id` in the namespace `prefix` (`chat::Router`).
+fn prefixed(prefix: Option<&str>, id: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{}::{}", prefix, unquote(id)),
+        None => id.to_string(),
+    }
+}
+
+/// Cluster ids keep their `cluster` prefix so the result is still a cluster.
+fn prefixed_cluster(prefix: Option<&str>, id: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("cluster_{}::{}", prefix, unquote(id)),
+        None => id.to_string(),
+    }
+}
+
+/// Copy `from` into `attributes` (keeping `html` in step), except `skip`.
+fn merge_attributes(
+    attributes: &mut BTreeMap<String, String>,
+    html: &mut BTreeSet<String>,
+    from: &BTreeMap<String, String>,
+    from_html: &BTreeSet<String>,
+    skip: &str,
+) {
+    for (k, v) in from.iter().filter(|(k, _)| k.as_str() != skip) {
+        attributes.insert(k.clone(), v.clone());
+        if from_html.contains(k) {
+            html.insert(k.clone());
+        } else {
+            html.remove(k);
+        }
+    }
+}
+
+/// Merge `parts` into one graph, then add `links` between them. Nodes are
+/// sorted by id and links keep part order, so composing the same files
+/// twice gives the same graph and deltas between compositions are stable.
+/// A node shared by several unprefixed parts keeps its first `origin`, with
+/// later attributes overriding earlier ones; root graph attributes merge the
+/// same way. The result is a `digraph` unless every part is undirected.
+pub fn compose(parts: &[ComposePart], links: &[CrossLink]) -> Result<GraphData, String> {
+    let mut nodes: Vec<GraphNode> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut composed = GraphData {
+        directed: parts.is_empty() || parts.iter().any(|p| p.graph.directed),
+        ..GraphData::default()
+    };
+
+    for part in parts {
+        let prefix = part.prefix.as_deref();
+        merge_attributes(&mut composed.attributes, &mut composed.html_attributes, &part.graph.attributes, &part.graph.html_attributes, "");
+        for node in &part.graph.nodes {
+            let id = prefixed(prefix, &node.id);
+            let subgraph = node.subgraph.as_deref().map(|c| prefixed_cluster(prefix, c));
+            match index.get(&id) {
+                Some(&i) => {
+                    let existing = &mut nodes[i];
+                    merge_attributes(&mut existing.attributes, &mut existing.html_attributes, &node.attributes, &node.html_attributes, ORIGIN_ATTRIBUTE);
+                    existing.subgraph = existing.subgraph.take().or(subgraph);
+                    // The label and colors derive from the merged attributes.
+                    existing.display_label = Some(node_label(existing, None));
+                    existing.colors = resolve_colors(&existing.attributes);
+                }
+                None => {
+                    let mut attributes = node.attributes.clone();
+                    attributes.insert(ORIGIN_ATTRIBUTE.to_string(), part.origin.clone());
+                    index.insert(id.clone(), nodes.len());
+                    nodes.push(GraphNode {
+                        name: id.clone(),
+                        id,
+                        attributes,
+                        html_attributes: node.html_attributes.clone(),
+                        cluster: None,
+                        metrics: BTreeMap::new(),
+                        subgraph,
+                        display_label: node.display_label.clone(),
+                        colors: node.colors.clone(),
+                    });
+                }
+            }
+        }
+        for link in &part.graph.links {
+            composed.links.push(GraphLink {
+                source: prefixed(prefix, &link.source),
+                target: prefixed(prefix, &link.target),
+                ..link.clone()
+            });
+        }
+        for cluster in &part.graph.clusters {
+            let id = prefixed_cluster(prefix, &cluster.id);
+            if !composed.clusters.iter().any(|c| c.id == id) {
+                composed.clusters.push(GraphCluster {
+                    id,
+                    parent: cluster.parent.as_deref().map(|p| prefixed_cluster(prefix, p)),
+                    attributes: cluster.attributes.clone(),
+                    html_attributes: cluster.html_attributes.clone(),
+                });
+            }
+        }
+    }
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    composed.nodes = nodes;
+
+    let adj = Adjacency::new(&composed);
+    let mut cross = Vec::new();
+    for link in links {
+        let source = adj.ids[adj.find(&link.from)?].to_string();
+        let target = adj.ids[adj.find(&link.to)?].to_string();
+        let mut composed_link = GraphLink {
+            source,
+            target,
+            label: link.attributes.get("label").cloned(),
+            attributes: link.attributes.clone(),
+            html_attributes: BTreeSet::new(),
+            weight: None,
+            display_label: None,
+            colors: BTreeMap::new(),
+        };
+        composed_link.weight = link_weight(&composed_link, &WeightOptions::default());
+        composed_link.display_label = link_label(&composed_link, None, composed.directed);
+        composed_link.colors = resolve_colors(&composed_link.attributes);
+        cross.push(composed_link);
+    }
+    composed.links.extend(cross);
+    Ok(composed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{compute_delta, parse_graph};
+
+    fn part(origin: &str, prefix: Option<&str>, dot: &str) -> ComposePart {
+        ComposePart { origin: origin.to_string(), prefix: prefix.map(str::to_string), graph: parse_graph(dot).unwrap() }
+    }
+
+    fn node<'g>(graph: &'g GraphData, id: &str) -> &'g GraphNode {
+        graph.nodes.iter().find(|n| n.id == id).unwrap()
+    }
+
+    #[test]
+    fn prefixes_namespace_nodes_and_clusters() {
+        let parts = vec![
+            part("chat.dot", Some("chat"), "digraph { subgraph cluster_core { Router } Router -> Queue }"),
+            part("store.dot", Some("store"), "digraph { Router -> Database }"),
+        ];
+        let link = CrossLink { from: "chat::Queue".to_string(), to: "\"store::Database\"".to_string(), attributes: BTreeMap::new() };
+        let graph = compose(&parts, &[link]).unwrap();
+        let ids: Vec<&str> = graph.nodes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, vec!["chat::Queue", "chat::Router", "store::Database", "store::Router"]);
+        let edges: Vec<(&str, &str)> = graph.links.iter().map(|l| (l.source.as_str(), l.target.as_str())).collect();
+        assert_eq!(edges, vec![("chat::Router", "chat::Queue"), ("store::Router", "store::Database"), ("chat::Queue", "store::Database")]);
+        assert_eq!(graph.clusters[0].id, "cluster_chat::cluster_core");
+        assert_eq!(node(&graph, "chat::Router").subgraph.as_deref(), Some("cluster_chat::cluster_core"));
+        assert_eq!(node(&graph, "store::Router").attributes[ORIGIN_ATTRIBUTE], "store.dot");
+
+        let missing = CrossLink { from: "chat::Nope".to_string(), to: "chat::Queue".to_string(), attributes: BTreeMap::new() };
+        assert!(compose(&parts, &[missing]).is_err());
+    }
+
+    #[test]
+    fn later_parts_override_attributes() {
+        let parts = vec![
+            part("a.dot", None, "digraph { x [label=old, color=red, shape=box] }"),
+            part("b.dot", None, "digraph { x [label=new, color=blue] }"),
+        ];
+        let graph = compose(&parts, &[]).unwrap();
+        let x = node(&graph, "x");
+        assert_eq!((x.attributes["label"].as_str(), x.attributes["shape"].as_str()), ("new", "box"));
+        assert_eq!(x.attributes[ORIGIN_ATTRIBUTE], "a.dot");
+        assert_eq!(x.display_label.as_ref().unwrap().lines[0].text(), "new");
+        assert_eq!(x.colors["color"], resolve_colors(&parts[1].graph.nodes[0].attributes)["color"]);
+    }
+
+    #[test]
+    fn deltas_between_compositions_follow_the_parts() {
+        let chat = part("chat.dot", Some("chat"), "digraph { Router -> Queue }");
+        let before = compose(&[chat, part("store.dot", Some("store"), "digraph { Database }")], &[]).unwrap();
+        let chat = part("chat.dot", Some("chat"), "digraph { Router -> Queue }");
+        let after = compose(&[chat, part("store.dot", Some("store"), "digraph { Database -> Cache }")], &[]).unwrap();
+        let delta = compute_delta(&before, &after);
+        assert_eq!(delta.added_nodes, vec!["store::Cache"]);
+        assert!(delta.removed_nodes.is_empty() && delta.removed_edges.is_empty());
+        assert_eq!(delta.added_edges, vec![("store::Database".to_string(), "store::Cache".to_string())]);
+    }
+}
@@ -1,24 +1,120 @@
-use dot_parser::{ast, canonical};
+use crate::color::{assign_colors, Rgba};
+use crate::interned::{IndexedDelta, IndexedGraph, Interner};
+use crate::label::{decode_labels, Label};
+use crate::weight::{assign_weights, WeightOptions};
+use dot_parser::ast::{self, either::Either};
 use serde::{Serialize, Deserialize};
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct GraphNode {
     pub id: String,
     pub name: String,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+    /// Attributes whose value is an HTML string (`label=<<B>x</B>>`) rather
+    /// than a quoted one; their values keep the outer `<...>`.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub html_attributes: BTreeSet<String>,
+    /// Community id, set by `detect_communities`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<usize>,
+    /// Numeric scores such as centralities, set by `compute_centrality`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metrics: BTreeMap<String, f64>,
+    /// Id of the innermost `subgraph cluster*` the node is first mentioned in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subgraph: Option<String>,
+    /// Decoded `label` (or `\N`), set by `parse_graph`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_label: Option<Label>,
+    /// Resolved color attributes, see `assign_colors`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub colors: BTreeMap<String, Rgba>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct GraphLink {
     pub source: String,
     pub target: String,
     pub label: Option<String>,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+    /// Attributes whose value is an HTML string (`label=<<B>x</B>>`) rather
+    /// than a quoted one; their values keep the outer `<...>`.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub html_attributes: BTreeSet<String>,
+    /// Numeric weight from the link's attributes, see `assign_weights`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
+    /// Decoded `label`, set by `parse_graph`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_label: Option<Label>,
+    /// Resolved color attributes, see `assign_colors`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub colors: BTreeMap<String, Rgba>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// A `subgraph cluster*` of the DOT source, in declaration order.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GraphCluster {
+    pub id: String,
+    /// The enclosing cluster, if nested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Graph attributes set inside the cluster, such as its `label`.
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+    /// Attributes whose value is an HTML string (`label=<<B>x</B>>`) rather
+    /// than a quoted one; their values keep the outer `<...>`.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub html_attributes: BTreeSet<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct GraphData {
     pub nodes: Vec<GraphNode>,
     pub links: Vec<GraphLink>,
+    #[serde(default)]
+    pub clusters: Vec<GraphCluster>,
+    /// `false` for an undirected `graph`.
+    #[serde(default = "default_directed")]
+    pub directed: bool,
+    /// Attributes of the root graph, such as `rankdir`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+    /// Attributes whose value is an HTML string (`label=<<B>x</B>>`) rather
+    /// than a quoted one; their values keep the outer `<...>`.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub html_attributes: BTreeSet<String>,
+}
+
+fn default_directed() -> bool {
+    true
+}
+
+impl Default for GraphData {
+    /// An empty `digraph`.
+    fn default() -> Self {
+        GraphData {
+            nodes: Vec::new(),
+            links: Vec::new(),
+            clusters: Vec::new(),
+            directed: default_directed(),
+            attributes: BTreeMap::new(),
+            html_attributes: BTreeSet::new(),
+        }
+    }
+}
+
+/// A node present in both graphs whose cluster changed; `None` is outside any cluster.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ClusterMove {
+    pub node: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -27,67 +123,726 @@ pub struct GraphDelta {
     pub removed_nodes: Vec<String>,
     pub added_edges: Vec<(String, String)>,
     pub removed_edges: Vec<(String, String)>,
+    #[serde(default)]
+    pub moved_nodes: Vec<ClusterMove>,
+    #[serde(default)]
+    pub added_clusters: Vec<String>,
+    #[serde(default)]
+    pub removed_clusters: Vec<String>,
+}
+
+/// Strip the surrounding quotes of a DOT ID and unescape `\"`.
+pub(crate) fn unquote(value: &str) -> String {
+    if is_quoted(value) {
+        unescape_quotes(strip_html_mark(&value[1..value.len() - 1]).0)
+    } else {
+        value.to_string()
+    }
+}
+
+fn is_quoted(value: &str) -> bool {
+    value.len() >= 2 && value.starts_with('"') && value.ends_with('"')
+}
+
+/// The parser strips the quotes of attribute values but keeps their escapes.
+fn unescape_quotes(value: &str) -> String {
+    value.replace("\\\"", "\"")
+}
+
+/// An attribute value without its quotes, and whether it was written as an
+/// HTML string (`<...>`, kept in `text`) rather than a quoted one, so that
+/// `label="<init>"` stays plain text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Value {
+    pub text: String,
+    pub html: bool,
+}
+
+impl Value {
+    /// The value of a DOT ID as written in the (marked) source, see `mark_html_ids`.
+    pub fn from_id(raw: &str) -> Self {
+        let html = is_quoted(raw) && strip_html_mark(&raw[1..raw.len() - 1]).1;
+        Value { text: unquote(raw), html }
+    }
+}
+
+pub(crate) type Attr = (String, Value);
+pub(crate) type Stmt = ast::Stmt<Attr>;
+pub(crate) type Subgraph = ast::Subgraph<Attr>;
+
+/// Starts the quoted strings `mark_html_ids` makes out of HTML IDs.
+const HTML_MARK: char = '\u{1}';
+
+/// Split the text of a quoted string into its text and whether it was an
+/// HTML ID; a doubled mark is a quoted string that began with the mark.
+fn strip_html_mark(text: &str) -> (&str, bool) {
+    match text.strip_prefix(HTML_MARK) {
+        Some(rest) if rest.starts_with(HTML_MARK) => (rest, false),
+        Some(rest) => (rest, true),
+        None => (text, false),
+    }
 }
 
-/// Preprocess DOT text to strip out `//` comments (full-line and inline).
-fn strip_comments(dot: &str) -> String {
-    dot.lines()
-        .map(|line| {
-            let trimmed = line.trim_start();
-            if trimmed.starts_with("//") {
-                ""
-            } else if let Some(idx) = line.find("//") {
-                &line[..idx]
-            } else {
-                line
+/// dot-parser only accepts HTML IDs without nested tags (`<x>`, not
+/// `<<B>x</B>>`), and hands them out like quoted strings. So every HTML ID
+/// is rewritten as a quoted string starting with `HTML_MARK`, and quoted
+/// strings that already start with it get it doubled.
+fn mark_html_ids(dot: &str) -> Cow<'_, str> {
+    if !dot.contains('<') && !dot.contains(HTML_MARK) {
+        return Cow::Borrowed(dot);
+    }
+    let mut out = String::with_capacity(dot.len());
+    let mut rest = dot;
+    while let Some(c) = rest.chars().next() {
+        let skip = if c == '"' {
+            // Quoted string, up to the first unescaped quote.
+            let mut end = rest.len();
+            let mut escaped = false;
+            for (i, c) in rest.char_indices().skip(1) {
+                match c {
+                    '\\' if !escaped => escaped = true,
+                    '"' if !escaped => {
+                        end = i + 1;
+                        break;
+                    }
+                    _ => escaped = false,
+                }
             }
-        })
-        .collect::<Vec<_>>()
-        .join("\n")
+            if rest[1..].starts_with(HTML_MARK) {
+                out.push('"');
+                out.push(HTML_MARK);
+                out.push_str(&rest[1..end]);
+                rest = &rest[end..];
+                continue;
+            }
+            end
+        } else if rest.starts_with("//") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if rest.starts_with("/*") {
+            rest.find("*/").map_or(rest.len(), |e| e + 2)
+        } else if c == '<' {
+            let mut depth = 0;
+            let mut end = None;
+            for (i, c) in rest.char_indices() {
+                match c {
+                    '<' => depth += 1,
+                    '>' => depth -= 1,
+                    _ => {}
+                }
+                if depth == 0 {
+                    end = Some(i + 1);
+                    break;
+                }
+            }
+            match end {
+                Some(end) => {
+                    out.push('"');
+                    out.push(HTML_MARK);
+                    out.push_str(&rest[..end].replace('"', "\\\""));
+                    out.push('"');
+                    rest = &rest[end..];
+                    continue;
+                }
+                None => rest.len(),
+            }
+        } else {
+            c.len_utf8()
+        };
+        out.push_str(&rest[..skip]);
+        rest = &rest[skip..];
+    }
+    Cow::Owned(out)
+}
+
+/// Parse DOT into its syntax tree, with attributes as unquoted `Value`s.
+/// Node and subgraph ids are left as written; `unquote` them.
+pub(crate) fn parse_ast(dot: &str) -> Result<ast::Graph<Attr>, String> {
+    let dot = mark_html_ids(dot);
+    let graph = ast::Graph::try_from(dot.as_ref()).map_err(|e| format!("DOT syntax error: {}", e))?;
+    Ok(graph.filter_map(&|(k, v)| {
+        let (k, v): (String, String) = (k.into(), v.into());
+        let (text, html) = strip_html_mark(&v);
+        Some((unescape_quotes(strip_html_mark(&k).0), Value { text: unescape_quotes(text), html }))
+    }))
+}
+
+fn list_attributes(list: &ast::AttrList<Attr>) -> impl Iterator<Item = (&String, &Value)> {
+    list.elems.iter().flat_map(|alist| alist.elems.iter()).map(|(k, v)| (k, v))
+}
+
+fn to_attributes(attr: &Option<ast::AttrList<Attr>>) -> BTreeMap<String, Value> {
+    attr.iter()
+        .flat_map(list_attributes)
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+/// Set `values` in `attributes`, keeping `html` (the `html_attributes`) in step.
+fn set_attributes<'v>(
+    attributes: &mut BTreeMap<String, String>,
+    html: &mut BTreeSet<String>,
+    values: impl IntoIterator<Item = (&'v String, &'v Value)>,
+) {
+    for (k, v) in values {
+        attributes.insert(k.clone(), v.text.clone());
+        if v.html {
+            html.insert(k.clone());
+        } else {
+            html.remove(k);
+        }
+    }
+}
+
+/// Where a statement sits: the innermost enclosing cluster, whether graph
+/// attributes set here are that cluster's (or the root graph's) own, not
+/// those of a plain subgraph in it, and the `node`, `edge` and `graph` defaults in effect. Defaults
+/// apply to what is created after them, in this (sub)graph and those nested
+/// in it, like in Graphviz.
+#[derive(Clone, Default)]
+struct Scope {
+    cluster: Option<usize>,
+    own: bool,
+    node_defaults: BTreeMap<String, Value>,
+    edge_defaults: BTreeMap<String, Value>,
+    graph_defaults: BTreeMap<String, Value>,
+}
+
+/// Accumulates `GraphData` while walking the syntax tree.
+#[derive(Default)]
+struct Builder {
+    nodes: Vec<GraphNode>,
+    node_index: HashMap<String, usize>,
+    links: Vec<GraphLink>,
+    clusters: Vec<GraphCluster>,
+    cluster_index: HashMap<String, usize>,
+    attributes: BTreeMap<String, String>,
+    html_attributes: BTreeSet<String>,
+}
+
+impl Builder {
+    /// Get or create the node `id` (unquoted, so `a` and `"a"` are one node),
+    /// placing it in `scope`'s cluster if it has none yet. New nodes start
+    /// with the scope's node defaults.
+    fn node(&mut self, id: &str, scope: &Scope) -> &mut GraphNode {
+        let i = match self.node_index.get(id) {
+            Some(&i) => i,
+            None => {
+                self.node_index.insert(id.to_string(), self.nodes.len());
+                let mut node = GraphNode {
+                    id: id.to_string(),
+                    name: id.to_string(),
+                    attributes: BTreeMap::new(),
+                    html_attributes: BTreeSet::new(),
+                    cluster: None,
+                    metrics: BTreeMap::new(),
+                    subgraph: None,
+                    display_label: None,
+                    colors: BTreeMap::new(),
+                };
+                set_attributes(&mut node.attributes, &mut node.html_attributes, &scope.node_defaults);
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        if let (None, Some(c)) = (&self.nodes[i].subgraph, scope.cluster) {
+            self.nodes[i].subgraph = Some(self.clusters[c].id.clone());
+        }
+        &mut self.nodes[i]
+    }
+
+    fn graph_attribute(&mut self, scope: &mut Scope, key: String, value: &Value) {
+        match (scope.cluster, scope.own) {
+            (Some(c), true) => {
+                let cluster = &mut self.clusters[c];
+                set_attributes(&mut cluster.attributes, &mut cluster.html_attributes, [(&key, value)]);
+            }
+            (None, true) => set_attributes(&mut self.attributes, &mut self.html_attributes, [(&key, value)]),
+            (_, false) => {}
+        }
+        scope.graph_defaults.insert(key, value.clone());
+    }
+
+    /// Walk statements, returning the ids of the nodes they mention.
+    fn stmts(&mut self, stmts: &[Stmt], mut scope: Scope) -> Vec<String> {
+        let mut mentioned = Vec::new();
+        for stmt in stmts {
+            match stmt {
+                ast::Stmt::NodeStmt(stmt) => {
+                    let id = unquote(&stmt.node.id);
+                    let attributes = to_attributes(&stmt.attr);
+                    let node = self.node(&id, &scope);
+                    set_attributes(&mut node.attributes, &mut node.html_attributes, &attributes);
+                    mentioned.push(id);
+                }
+                ast::Stmt::EdgeStmt(stmt) => {
+                    let (mut attributes, mut html) = (BTreeMap::new(), BTreeSet::new());
+                    set_attributes(&mut attributes, &mut html, &scope.edge_defaults);
+                    set_attributes(&mut attributes, &mut html, &to_attributes(&stmt.attr));
+                    let mut from = self.endpoint(&stmt.from, &scope);
+                    mentioned.extend(from.iter().cloned());
+                    let mut rhs = Some(&stmt.next);
+                    while let Some(next) = rhs {
+                        let to = self.endpoint(&next.to, &scope);
+                        for source in &from {
+                            for target in &to {
+                                self.links.push(GraphLink {
+                                    source: source.clone(),
+                                    target: target.clone(),
+                                    label: attributes.get("label").cloned(),
+                                    attributes: attributes.clone(),
+                                    html_attributes: html.clone(),
+                                    weight: None,
+                                    display_label: None,
+                                    colors: BTreeMap::new(),
+                                });
+                            }
+                        }
+                        mentioned.extend(to.iter().cloned());
+                        from = to;
+                        rhs = next.next.as_deref();
+                    }
+                }
+                ast::Stmt::AttrStmt(ast::AttrStmt::Graph(list)) => {
+                    for (k, v) in list_attributes(list) {
+                        self.graph_attribute(&mut scope, k.clone(), v);
+                    }
+                }
+                ast::Stmt::AttrStmt(ast::AttrStmt::Node(list)) => {
+                    scope.node_defaults.extend(list_attributes(list).map(|(k, v)| (k.clone(), v.clone())));
+                }
+                ast::Stmt::AttrStmt(ast::AttrStmt::Edge(list)) => {
+                    scope.edge_defaults.extend(list_attributes(list).map(|(k, v)| (k.clone(), v.clone())));
+                }
+                ast::Stmt::IDEq(k, v) => self.graph_attribute(&mut scope, unquote(k), &Value::from_id(v)),
+                ast::Stmt::Subgraph(sub) => mentioned.extend(self.subgraph(sub, &scope)),
+            }
+        }
+        mentioned
+    }
+
+    /// The nodes an edge endpoint stands for: one node, or all nodes of a subgraph.
+    fn endpoint(&mut self, end: &Either<ast::NodeID, Subgraph>, scope: &Scope) -> Vec<String> {
+        match end {
+            Either::Left(node) => {
+                let id = unquote(&node.id);
+                self.node(&id, scope);
+                vec![id]
+            }
+            Either::Right(sub) => {
+                let mut ids = self.subgraph(sub, scope);
+                let mut seen = HashSet::new();
+                ids.retain(|id| seen.insert(id.clone()));
+                ids
+            }
+        }
+    }
+
+    /// Walk a subgraph in a copy of `scope`, so its defaults don't leak out.
+    /// A new cluster starts with the graph attributes in effect around it.
+    fn subgraph(&mut self, sub: &Subgraph, scope: &Scope) -> Vec<String> {
+        let mut inner = scope.clone();
+        inner.own = false;
+        if let Some(id) = sub.id.as_deref().map(unquote).filter(|id| id.starts_with("cluster")) {
+            let c = match self.cluster_index.get(&id) {
+                Some(&c) => c,
+                None => {
+                    let parent = scope.cluster.map(|p| self.clusters[p].id.clone());
+                    self.cluster_index.insert(id.clone(), self.clusters.len());
+                    let mut cluster = GraphCluster {
+                        id: id.clone(),
+                        parent,
+                        attributes: BTreeMap::new(),
+                        html_attributes: BTreeSet::new(),
+                    };
+                    set_attributes(&mut cluster.attributes, &mut cluster.html_attributes, &scope.graph_defaults);
+                    self.clusters.push(cluster);
+                    self.clusters.len() - 1
+                }
+            };
+            inner.cluster = Some(c);
+            inner.own = true;
+        }
+        self.stmts(&sub.stmts.stmts, inner)
+    }
 }
 
 /// Parse DOT into GraphData
 pub fn parse_graph(dot: &str) -> Result<GraphData, String> {
-    let cleaned = strip_comments(dot);
+    Ok(graph_from_ast(&parse_ast(dot)?))
+}
 
-    let ast_graph = ast::Graph::read_dot(&cleaned)
-        .map_err(|e| format!("DOT syntax error: {}", e))?;
-    let graph = canonical::Graph::from(ast_graph);
+/// `GraphData` of a syntax tree from `parse_ast`.
+pub(crate) fn graph_from_ast(ast_graph: &ast::Graph<Attr>) -> GraphData {
+    let mut builder = Builder::default();
+    builder.stmts(&ast_graph.stmts.stmts, Scope { own: true, ..Scope::default() });
 
-    let nodes = graph.nodes.set.iter().map(|(id, _)| GraphNode {
-        id: id.to_string(),
-        name: id.to_string(),
-    }).collect();
+    let mut nodes = builder.nodes;
+    // Sort nodes by id so output doesn't depend on declaration order.
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
 
-    let links = graph.edges.set.iter().map(|edge| GraphLink {
-        source: edge.from.to_string(),
-        target: edge.to.to_string(),
-        label: edge.attr.elems.iter()
-            .find(|(k, _)| *k == "label")
-            .map(|(_, v)| v.to_string()),
-    }).collect();
+    let mut graph = GraphData {
+        nodes,
+        links: builder.links,
+        clusters: builder.clusters,
+        directed: ast_graph.is_digraph,
+        attributes: builder.attributes,
+        html_attributes: builder.html_attributes,
+    };
+    assign_weights(&mut graph, &WeightOptions::default());
+    decode_labels(&mut graph, ast_graph.name.as_deref());
+    assign_colors(&mut graph);
+    graph
+}
 
-    Ok(GraphData { nodes, links })
+/// Quote a DOT ID unless it is already quoted, a plain identifier or a
+/// numeral. Text in `<...>` is quoted too; see `write_value` for HTML strings.
+pub(crate) fn quote_id(id: &str) -> String {
+    let plain = id.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    let numeral = !id.is_empty() && id.parse::<f64>().is_ok()
+        && id.chars().all(|c| c.is_ascii_digit() || c == '.' || c == '-');
+    if plain || numeral || is_quoted(id) {
+        id.to_string()
+    } else {
+        format!("\"{}\"", id.replace('"', "\\\""))
+    }
 }
 
-fn to_sets(graph: &GraphData) -> (HashSet<String>, HashSet<(String, String)>) {
-    let nodes: HashSet<_> = graph.nodes.iter().map(|n| n.id.clone()).collect();
-    let edges: HashSet<_> = graph.links.iter()
-        .map(|e| (e.source.clone(), e.target.clone()))
-        .collect();
-    (nodes, edges)
+/// An attribute value as DOT: HTML strings as they are, others quoted as needed.
+fn write_value(value: &str, html: bool) -> String {
+    if html && value.starts_with('<') && value.ends_with('>') {
+        value.to_string()
+    } else {
+        quote_id(value)
+    }
 }
 
-/// Compute delta between two graphs
+fn write_attributes(out: &mut String, attributes: &BTreeMap<String, String>, html: &BTreeSet<String>) {
+    if !attributes.is_empty() {
+        let list: Vec<String> = attributes.iter()
+            .map(|(k, v)| format!("{}={}", quote_id(k), write_value(v, html.contains(k))))
+            .collect();
+        out.push_str(&format!(" [{}]", list.join(", ")));
+    }
+}
+
+fn write_node(out: &mut String, node: &GraphNode, indent: &str) {
+    out.push_str(indent);
+    out.push_str(&quote_id(&node.id));
+    write_attributes(out, &node.attributes, &node.html_attributes);
+    out.push_str(";\n");
+}
+
+/// Write `cluster` as a subgraph holding its member nodes and nested clusters.
+fn write_cluster(out: &mut String, graph: &GraphData, cluster: &GraphCluster, depth: usize) {
+    let indent = "    ".repeat(depth);
+    out.push_str(&format!("{}subgraph {} {{\n", indent, quote_id(&cluster.id)));
+    for (k, v) in &cluster.attributes {
+        out.push_str(&format!("{}    {}={};\n", indent, quote_id(k), write_value(v, cluster.html_attributes.contains(k))));
+    }
+    for node in graph.nodes.iter().filter(|n| n.subgraph.as_ref() == Some(&cluster.id)) {
+        write_node(out, node, &format!("{}    ", indent));
+    }
+    for child in graph.clusters.iter().filter(|c| c.parent.as_ref() == Some(&cluster.id)) {
+        write_cluster(out, graph, child, depth + 1);
+    }
+    out.push_str(&format!("{}}}\n", indent));
+}
+
+/// Serialize GraphData back to DOT, as a `digraph` or `graph`, with the
+/// root graph's attributes first. Clusters become nested `subgraph` blocks
+/// holding their member nodes.
+pub fn write_dot(graph: &GraphData) -> String {
+    let known: HashSet<&str> = graph.clusters.iter().map(|c| c.id.as_str()).collect();
+    let (kind, edge_op) = if graph.directed { ("digraph", "->") } else { ("graph", "--") };
+    let mut out = format!("{} {{\n", kind);
+    for (k, v) in &graph.attributes {
+        out.push_str(&format!("    {}={};\n", quote_id(k), write_value(v, graph.html_attributes.contains(k))));
+    }
+    for node in &graph.nodes {
+        if !node.subgraph.as_deref().is_some_and(|c| known.contains(c)) {
+            write_node(&mut out, node, "    ");
+        }
+    }
+    for cluster in &graph.clusters {
+        if !cluster.parent.as_deref().is_some_and(|p| known.contains(p)) {
+            write_cluster(&mut out, graph, cluster, 1);
+        }
+    }
+    for link in &graph.links {
+        out.push_str(&format!("    {} {} {}", quote_id(&link.source), edge_op, quote_id(&link.target)));
+        write_attributes(&mut out, &link.attributes, &link.html_attributes);
+        out.push_str(";\n");
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Compute delta between two graphs. Ids are interned and the node and edge
+/// sets compared as sorted integer keys, so only changed ids are cloned.
+/// Results are ordered by first appearance of each id in `g1`, then `g2`.
 pub fn compute_delta(g1: &GraphData, g2: &GraphData) -> GraphDelta {
-    let (nodes1, edges1) = to_sets(g1);
-    let (nodes2, edges2) = to_sets(g2);
+    let mut interner = Interner::with_capacity(g1.nodes.len() + g2.nodes.len());
+    let old = IndexedGraph::new(g1, &mut interner);
+    let new = IndexedGraph::new(g2, &mut interner);
+    let mut delta = IndexedDelta::between(&old, &new).resolve(&interner);
+    cluster_changes(g1, g2, |id| id, &mut delta);
+    delta
+}
+
+/// Compute the delta between two graphs after renaming the nodes of `g1`
+/// through `mapping` (e.g. from `find_isomorphism`); unmapped ids are kept.
+pub fn compute_delta_mapped(g1: &GraphData, g2: &GraphData, mapping: &BTreeMap<String, String>) -> GraphDelta {
+    let mut interner = Interner::with_capacity(g1.nodes.len() + g2.nodes.len());
+    let rename = |id| mapping.get(id).map_or(id, String::as_str);
+    let old = IndexedGraph::with_rename(g1, &mut interner, rename);
+    let new = IndexedGraph::new(g2, &mut interner);
+    let mut delta = IndexedDelta::between(&old, &new).resolve(&interner);
+    cluster_changes(g1, g2, rename, &mut delta);
+    delta
+}
+
+/// Fill in the cluster part of `delta`: clusters only in one graph, and
+/// nodes of both (after `rename`) whose cluster differs, in `g2` order.
+fn cluster_changes<'a, F>(g1: &'a GraphData, g2: &GraphData, rename: F, delta: &mut GraphDelta)
+where
+    F: Fn(&'a str) -> &'a str,
+{
+    let clustered = |g: &GraphData| !g.clusters.is_empty() || g.nodes.iter().any(|n| n.subgraph.is_some());
+    if !clustered(g1) && !clustered(g2) {
+        return;
+    }
+
+    let old: HashSet<&str> = g1.clusters.iter().map(|c| c.id.as_str()).collect();
+    let new: HashSet<&str> = g2.clusters.iter().map(|c| c.id.as_str()).collect();
+    delta.added_clusters = g2.clusters.iter().filter(|c| !old.contains(c.id.as_str())).map(|c| c.id.clone()).collect();
+    delta.removed_clusters = g1.clusters.iter().filter(|c| !new.contains(c.id.as_str())).map(|c| c.id.clone()).collect();
+
+    let before: HashMap<&str, &Option<String>> = g1.nodes.iter().map(|n| (rename(&n.id), &n.subgraph)).collect();
+    delta.moved_nodes = g2.nodes.iter()
+        .filter_map(|n| match before.get(n.id.as_str()) {
+            Some(&from) if *from != n.subgraph => {
+                Some(ClusterMove { node: n.id.clone(), from: from.clone(), to: n.subgraph.clone() })
+            }
+            _ => None,
+        })
+        .collect();
+}
+
+/// Index-based adjacency view over a `GraphData`, shared by the graph algorithms.
+/// Links whose endpoints are not declared nodes are skipped.
+pub(crate) struct Adjacency<'a> {
+    pub ids: Vec<&'a str>,
+    pub index: HashMap<&'a str, usize>,
+    /// For each node, `(target, link index)` of its outgoing links.
+    pub out: Vec<Vec<(usize, usize)>>,
+    /// For each node, `(source, link index)` of its incoming links.
+    pub inc: Vec<Vec<(usize, usize)>>,
+}
+
+impl<'a> Adjacency<'a> {
+    pub fn new(graph: &'a GraphData) -> Self {
+        let ids: Vec<&str> = graph.nodes.iter().map(|n| n.id.as_str()).collect();
+        let index: HashMap<&str, usize> = ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+        let mut out = vec![Vec::new(); ids.len()];
+        let mut inc = vec![Vec::new(); ids.len()];
+
+        for (l, link) in graph.links.iter().enumerate() {
+            if let (Some(&s), Some(&t)) = (index.get(link.source.as_str()), index.get(link.target.as_str())) {
+                out[s].push((t, l));
+                inc[t].push((s, l));
+            }
+        }
+
+        Adjacency { ids, index, out, inc }
+    }
+
+    /// Look up a node by id, also accepting the id in DOT quotes.
+    pub fn find(&self, id: &str) -> Result<usize, String> {
+        self.index.get(id).or_else(|| self.index.get(unquote(id).as_str())).copied()
+            .ok_or_else(|| format!("Unknown node: {}", id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Neighbours of `n` ignoring edge direction.
+    pub fn neighbors(&self, n: usize) -> impl Iterator<Item = usize> + '_ {
+        self.out[n].iter().chain(self.inc[n].iter()).map(|&(m, _)| m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(graph: &GraphData) -> Vec<&str> {
+        graph.nodes.iter().map(|n| n.id.as_str()).collect()
+    }
+
+    fn edges(graph: &GraphData) -> Vec<(&str, &str)> {
+        graph.links.iter().map(|l| (l.source.as_str(), l.target.as_str())).collect()
+    }
+
+    #[test]
+    fn edge_chains_and_subgraph_endpoints_expand() {
+        let graph = parse_graph("digraph { c -> b -> a; d -> { e f }; subgraph s { g } }").unwrap();
+        assert_eq!(ids(&graph), vec!["a", "b", "c", "d", "e", "f", "g"]);
+        assert_eq!(edges(&graph), vec![("c", "b"), ("b", "a"), ("d", "e"), ("d", "f")]);
+    }
+
+    #[test]
+    fn attributes_are_unquoted() {
+        let graph = parse_graph(r#"digraph { a [label="say \"hi\"", shape=box]; a -> b [label="x y"] }"#).unwrap();
+        assert_eq!(graph.nodes[0].attributes["label"], r#"say "hi""#);
+        assert_eq!(graph.nodes[0].attributes["shape"], "box");
+        assert_eq!(graph.links[0].label.as_deref(), Some("x y"));
+    }
+
+    #[test]
+    fn comments_are_ignored() {
+        let dot = "digraph {\n  // a -> z\n  a -> b; /* b -> z\n */ b -> c // c -> z\n}";
+        assert_eq!(edges(&parse_graph(dot).unwrap()), vec![("a", "b"), ("b", "c")]);
+    }
+
+    #[test]
+    fn quoted_and_bare_ids_are_one_node() {
+        let graph = parse_graph(r#"digraph G { "b"->"c"  a [shape=box]  "a" -> b  subgraph "cluster_x" { c } subgraph cluster_x { d } }"#).unwrap();
+        assert_eq!(ids(&graph), vec!["a", "b", "c", "d"]);
+        assert_eq!(edges(&graph), vec![("b", "c"), ("a", "b")]);
+        assert_eq!(graph.nodes[0].attributes["shape"], "box");
+        assert_eq!(graph.clusters.len(), 1);
+        assert_eq!(graph.nodes[3].subgraph.as_deref(), Some("cluster_x"));
+        let spaced = parse_graph(r#"digraph { "a b" -> c }"#).unwrap();
+        assert_eq!(ids(&spaced), vec!["a b", "c"]);
+        assert!(write_dot(&spaced).contains(r#""a b" -> c"#));
+    }
+
+    #[test]
+    fn html_strings_are_kept_apart_from_quoted_ones() {
+        let dot = "digraph { a [label=\"<init>\"]; b [label=<<B>x</B>>]; c [label=<y>, xlabel=\"\u{1}<z>\"]; a -> b [label=<e>] }";
+        let graph = parse_graph(dot).unwrap();
+        let (a, b, c) = (&graph.nodes[0], &graph.nodes[1], &graph.nodes[2]);
+        assert_eq!(a.attributes["label"], "<init>");
+        assert!(a.html_attributes.is_empty());
+        let label = a.display_label.as_ref().unwrap();
+        assert!(!label.html);
+        assert_eq!(label.text(), "<init>");
+        assert_eq!(b.attributes["label"], "<<B>x</B>>");
+        assert!(b.display_label.as_ref().unwrap().html);
+        assert_eq!(c.html_attributes.iter().collect::<Vec<_>>(), vec!["label"]);
+        assert_eq!(c.attributes["xlabel"], "\u{1}<z>");
+        assert!(graph.links[0].html_attributes.contains("label"));
+
+        let written = write_dot(&graph);
+        assert!(written.contains("label=\"<init>\""), "{}", written);
+        assert!(written.contains("label=<<B>x</B>>"), "{}", written);
+        let again = parse_graph(&written).unwrap();
+        for (before, after) in graph.nodes.iter().zip(&again.nodes) {
+            assert_eq!(before.attributes, after.attributes);
+            assert_eq!(before.html_attributes, after.html_attributes);
+        }
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        let error = parse_graph("digraph { a -> }").err().unwrap();
+        assert!(error.starts_with("DOT syntax error"), "{}", error);
+    }
+
+    #[test]
+    fn written_dot_parses_back() {
+        let graph = parse_graph(r#"digraph { "a b" [color=red]; "a b" -> c [label="l"] }"#).unwrap();
+        let again = parse_graph(&write_dot(&graph)).unwrap();
+        assert_eq!(ids(&again), ids(&graph));
+        assert_eq!(edges(&again), edges(&graph));
+        assert_eq!(again.nodes[0].attributes, graph.nodes[0].attributes);
+    }
+
+    fn attribute<'g>(graph: &'g GraphData, id: &str, key: &str) -> Option<&'g str> {
+        graph.nodes.iter().find(|n| n.id == id)?.attributes.get(key).map(String::as_str)
+    }
+
+    #[test]
+    fn defaults_apply_from_where_they_are_set() {
+        let dot = "digraph {
+            a; node [shape=box]; b;
+            subgraph s { node [color=red]; edge [style=dashed]; c -> d; b }
+            e; b -> e
+        }";
+        let graph = parse_graph(dot).unwrap();
+        assert_eq!(attribute(&graph, "a", "shape"), None);
+        assert_eq!(attribute(&graph, "b", "shape"), Some("box"));
+        // `b` exists before the subgraph, so its defaults don't reach it.
+        assert_eq!(attribute(&graph, "b", "color"), None);
+        for id in ["c", "d"] {
+            assert_eq!(attribute(&graph, id, "shape"), Some("box"));
+            assert_eq!(attribute(&graph, id, "color"), Some("red"));
+        }
+        assert_eq!(attribute(&graph, "e", "color"), None);
+        assert_eq!(graph.links[0].attributes.get("style").map(String::as_str), Some("dashed"));
+        assert!(graph.links[1].attributes.is_empty());
+    }
+
+    #[test]
+    fn clusters_start_with_the_graph_attributes_around_them() {
+        let dot = "digraph {
+            label=G; color=gray
+            subgraph cluster_outer { color=blue; subgraph { style=dotted; x } subgraph cluster_inner { y } }
+            z
+        }";
+        let graph = parse_graph(dot).unwrap();
+        let cluster = |id: &str| graph.clusters.iter().find(|c| c.id == id).unwrap();
+        let outer = &cluster("cluster_outer").attributes;
+        assert_eq!((outer["label"].as_str(), outer["color"].as_str()), ("G", "blue"));
+        // A plain subgraph's attributes are not the cluster's own.
+        assert!(!outer.contains_key("style"));
+        assert_eq!(cluster("cluster_inner").parent.as_deref(), Some("cluster_outer"));
+        assert_eq!(cluster("cluster_inner").attributes["color"], "blue");
+        let subgraph = |id: &str| graph.nodes.iter().find(|n| n.id == id).unwrap().subgraph.as_deref();
+        assert_eq!((subgraph("x"), subgraph("y"), subgraph("z")), (Some("cluster_outer"), Some("cluster_inner"), None));
+    }
+
+    #[test]
+    fn cluster_moves_are_reported() {
+        let old = parse_graph("digraph { subgraph cluster_a { x; y } subgraph cluster_gone { w } z }").unwrap();
+        let new = parse_graph("digraph { subgraph cluster_a { x } subgraph cluster_b { y; z } w }").unwrap();
+        let delta = compute_delta(&old, &new);
+        assert!(delta.added_nodes.is_empty() && delta.removed_nodes.is_empty());
+        assert_eq!(delta.added_clusters, vec!["cluster_b"]);
+        assert_eq!(delta.removed_clusters, vec!["cluster_gone"]);
+        let moved = |node: &str, from: Option<&str>, to: Option<&str>| ClusterMove {
+            node: node.to_string(),
+            from: from.map(str::to_string),
+            to: to.map(str::to_string),
+        };
+        assert_eq!(delta.moved_nodes, vec![
+            moved("w", Some("cluster_gone"), None),
+            moved("y", Some("cluster_a"), Some("cluster_b")),
+            moved("z", None, Some("cluster_b")),
+        ]);
 
-    let added_nodes = nodes2.difference(&nodes1).cloned().collect();
-    let removed_nodes = nodes1.difference(&nodes2).cloned().collect();
+        let flat = parse_graph("digraph { a -> b }").unwrap();
+        let delta = compute_delta(&flat, &flat);
+        assert!(delta.moved_nodes.is_empty() && delta.added_clusters.is_empty());
+    }
 
-    let added_edges = edges2.difference(&edges1).cloned().collect();
-    let removed_edges = edges1.difference(&edges2).cloned().collect();
+    #[test]
+    fn mapped_delta_compares_renamed_nodes() {
+        let old = parse_graph("digraph { subgraph cluster_a { a } a -> b; b -> c }").unwrap();
+        let new = parse_graph("digraph { subgraph cluster_a { y } x -> y; y -> z; z -> x }").unwrap();
+        let mapping: BTreeMap<String, String> = [("a", "x"), ("b", "y"), ("c", "z")].iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let delta = compute_delta_mapped(&old, &new, &mapping);
+        assert!(delta.added_nodes.is_empty() && delta.removed_nodes.is_empty());
+        assert!(delta.removed_edges.is_empty());
+        assert_eq!(delta.added_edges, vec![("z".to_string(), "x".to_string())]);
+        let movers: Vec<&str> = delta.moved_nodes.iter().map(|m| m.node.as_str()).collect();
+        assert_eq!(movers, vec!["x", "y"]);
 
-    GraphDelta { added_nodes, removed_nodes, added_edges, removed_edges }
+        let plain = compute_delta(&old, &new);
+        assert_eq!(plain.removed_nodes.len(), 3);
+    }
 }
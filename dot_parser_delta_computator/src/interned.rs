@@ -0,0 +1,247 @@
+//! Interned, index-based view of graphs for diffing. Node ids are interned to
+//! `u32`s shared by both sides of a diff, edges become `u64` keys, and the
+//! sorted key lists are compared with a single merge pass; strings are only
+//! materialized for the elements that changed.
+
+use crate::graph::{GraphData, GraphDelta};
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+/// Multiply-rotate hash (as in rustc's FxHash); several times faster than the
+/// default SipHash on short ids.
+#[derive(Default)]
+struct FxHasher(u64);
+
+impl Hasher for FxHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let word = u64::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3], chunk[4], chunk[5], chunk[6], chunk[7]]);
+            self.add(word);
+        }
+        for &b in chunks.remainder() {
+            self.add(b as u64);
+        }
+    }
+
+    fn write_u8(&mut self, b: u8) {
+        self.add(b as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl FxHasher {
+    fn add(&mut self, word: u64) {
+        self.0 = (self.0.rotate_left(5) ^ word).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+}
+
+/// Maps node ids to dense `u32` indices, borrowing the ids from the graphs.
+#[derive(Default)]
+pub struct Interner<'a> {
+    index: HashMap<&'a str, u32, BuildHasherDefault<FxHasher>>,
+    names: Vec<&'a str>,
+}
+
+impl<'a> Interner<'a> {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Interner {
+            index: HashMap::with_capacity_and_hasher(capacity, Default::default()),
+            names: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn intern(&mut self, id: &'a str) -> u32 {
+        if let Some(&i) = self.index.get(id) {
+            return i;
+        }
+        let i = self.names.len() as u32;
+        self.index.insert(id, i);
+        self.names.push(id);
+        i
+    }
+
+    pub fn get(&self, id: &str) -> Option<u32> {
+        self.index.get(id).copied()
+    }
+
+    pub fn name(&self, i: u32) -> &'a str {
+        self.names[i as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// Pack an edge into one sortable key: source in the high half, target in the low.
+pub fn edge_key(source: u32, target: u32) -> u64 {
+    (source as u64) << 32 | target as u64
+}
+
+pub fn edge_endpoints(key: u64) -> (u32, u32) {
+    ((key >> 32) as u32, key as u32)
+}
+
+/// Node and edge sets of a graph as sorted, deduplicated interned keys.
+pub struct IndexedGraph {
+    pub nodes: Vec<u32>,
+    pub edges: Vec<u64>,
+}
+
+impl IndexedGraph {
+    pub fn new<'a>(graph: &'a GraphData, interner: &mut Interner<'a>) -> Self {
+        IndexedGraph::with_rename(graph, interner, |id| id)
+    }
+
+    /// Build the index with every id first passed through `rename`.
+    pub fn with_rename<'a, F>(graph: &'a GraphData, interner: &mut Interner<'a>, rename: F) -> Self
+    where
+        F: Fn(&'a str) -> &'a str,
+    {
+        let mut nodes: Vec<u32> = graph.nodes.iter().map(|n| interner.intern(rename(&n.id))).collect();
+        let mut edges: Vec<u64> = graph.links.iter()
+            .map(|l| edge_key(interner.intern(rename(&l.source)), interner.intern(rename(&l.target))))
+            .collect();
+        nodes.sort_unstable();
+        nodes.dedup();
+        edges.sort_unstable();
+        edges.dedup();
+        IndexedGraph { nodes, edges }
+    }
+}
+
+/// Elements only in `old` and only in `new`, by a merge of two sorted lists.
+fn merge_diff<T: Ord + Copy>(old: &[T], new: &[T]) -> (Vec<T>, Vec<T>) {
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        match old[i].cmp(&new[j]) {
+            std::cmp::Ordering::Less => {
+                removed.push(old[i]);
+                i += 1;
+            }
+            std::cmp::Ordering::Greater => {
+                added.push(new[j]);
+                j += 1;
+            }
+            std::cmp::Ordering::Equal => {
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    removed.extend_from_slice(&old[i..]);
+    added.extend_from_slice(&new[j..]);
+    (removed, added)
+}
+
+/// A delta in interned form; `resolve` turns it into a `GraphDelta`.
+pub struct IndexedDelta {
+    pub added_nodes: Vec<u32>,
+    pub removed_nodes: Vec<u32>,
+    pub added_edges: Vec<u64>,
+    pub removed_edges: Vec<u64>,
+}
+
+impl IndexedDelta {
+    /// Both graphs must have been indexed with the same interner.
+    pub fn between(old: &IndexedGraph, new: &IndexedGraph) -> Self {
+        let (removed_nodes, added_nodes) = merge_diff(&old.nodes, &new.nodes);
+        let (removed_edges, added_edges) = merge_diff(&old.edges, &new.edges);
+        IndexedDelta { added_nodes, removed_nodes, added_edges, removed_edges }
+    }
+
+    pub fn resolve(&self, interner: &Interner) -> GraphDelta {
+        let node = |&i: &u32| interner.name(i).to_string();
+        let edge = |&key: &u64| {
+            let (s, t) = edge_endpoints(key);
+            (interner.name(s).to_string(), interner.name(t).to_string())
+        };
+        GraphDelta {
+            added_nodes: self.added_nodes.iter().map(node).collect(),
+            removed_nodes: self.removed_nodes.iter().map(node).collect(),
+            added_edges: self.added_edges.iter().map(edge).collect(),
+            removed_edges: self.removed_edges.iter().map(edge).collect(),
+            moved_nodes: Vec::new(),
+            added_clusters: Vec::new(),
+            removed_clusters: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{compute_delta, parse_graph};
+    use std::collections::BTreeSet;
+
+    type Sets = (BTreeSet<String>, BTreeSet<(String, String)>);
+
+    fn sets(graph: &GraphData) -> Sets {
+        let nodes = graph.nodes.iter().map(|n| n.id.clone()).collect();
+        let edges = graph.links.iter().map(|l| (l.source.clone(), l.target.clone())).collect();
+        (nodes, edges)
+    }
+
+    /// The delta computed on string sets, sorted.
+    fn string_delta(old: &GraphData, new: &GraphData) -> (Sets, Sets) {
+        let ((old_nodes, old_edges), (new_nodes, new_edges)) = (sets(old), sets(new));
+        let added = (&new_nodes - &old_nodes, &new_edges - &old_edges);
+        let removed = (&old_nodes - &new_nodes, &old_edges - &new_edges);
+        (added, removed)
+    }
+
+    fn sorted(delta: &GraphDelta) -> (Sets, Sets) {
+        let added = (delta.added_nodes.iter().cloned().collect(), delta.added_edges.iter().cloned().collect());
+        let removed = (delta.removed_nodes.iter().cloned().collect(), delta.removed_edges.iter().cloned().collect());
+        (added, removed)
+    }
+
+    #[test]
+    fn interned_delta_matches_string_sets() {
+        let old = parse_graph("digraph {
+            subgraph cluster_a { a; b } subgraph cluster_b { c }
+            a -> b; a -> b; b -> c; c -> a; c -> c; d -> a
+        }").unwrap();
+        let new = parse_graph("digraph {
+            subgraph cluster_a { a } subgraph cluster_c { b; e }
+            a -> b; b -> c; b -> c; c -> c; c -> c; e -> a; a -> e
+        }").unwrap();
+        for (g1, g2) in [(&old, &new), (&new, &old), (&old, &old)].iter() {
+            let delta = compute_delta(g1, g2);
+            assert_eq!(sorted(&delta), string_delta(g1, g2));
+            // Parallel links give one entry, however many there are.
+            let unique: BTreeSet<&(String, String)> = delta.added_edges.iter().chain(&delta.removed_edges).collect();
+            assert_eq!(unique.len(), delta.added_edges.len() + delta.removed_edges.len());
+        }
+
+        let delta = compute_delta(&old, &new);
+        assert_eq!(delta.removed_nodes, vec!["d"]);
+        assert_eq!(delta.added_nodes, vec!["e"]);
+        assert_eq!(delta.removed_edges, vec![("c".to_string(), "a".to_string()), ("d".to_string(), "a".to_string())]);
+        assert_eq!(delta.added_clusters, vec!["cluster_c"]);
+    }
+
+    #[test]
+    fn edge_keys_round_trip() {
+        let mut interner = Interner::new();
+        let (a, b) = (interner.intern("a"), interner.intern("b"));
+        assert_eq!(interner.intern("a"), a);
+        assert_eq!((interner.len(), interner.get("b"), interner.get("z")), (2, Some(b), None));
+        assert_eq!(edge_endpoints(edge_key(b, a)), (b, a));
+        assert!(edge_key(a, b) < edge_key(b, a));
+        assert_eq!(edge_endpoints(edge_key(u32::MAX, 7)), (u32::MAX, 7));
+    }
+}
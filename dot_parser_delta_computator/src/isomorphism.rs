@@ -0,0 +1,309 @@
+//! Structural isomorphism between two graphs, ignoring node ids: a VF2-style
+//! backtracking search over directed multigraphs, optionally requiring labels
+//! or all attributes of matched nodes and edges to agree.
+
+use crate::graph::{Adjacency, GraphData, GraphLink, GraphNode};
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct IsomorphismOptions {
+    /// Matched nodes and edges must have the same `label`.
+    pub match_labels: bool,
+    /// Matched nodes and edges must have identical attributes.
+    pub match_attributes: bool,
+    /// Give up after this many candidate pairs have been tried.
+    pub max_steps: usize,
+}
+
+impl Default for IsomorphismOptions {
+    fn default() -> Self {
+        IsomorphismOptions { match_labels: false, match_attributes: false, max_steps: 1_000_000 }
+    }
+}
+
+/// Node id in the first graph -> node id in the second.
+pub type NodeMapping = BTreeMap<String, String>;
+
+fn attributes_key(attributes: &BTreeMap<String, String>, options: &IsomorphismOptions) -> String {
+    if options.match_attributes {
+        serde_json::to_string(attributes).unwrap_or_default()
+    } else if options.match_labels {
+        attributes.get("label").cloned().unwrap_or_default()
+    } else {
+        String::new()
+    }
+}
+
+fn node_key(node: &GraphNode, options: &IsomorphismOptions) -> String {
+    attributes_key(&node.attributes, options)
+}
+
+fn link_key(link: &GraphLink, options: &IsomorphismOptions) -> String {
+    attributes_key(&link.attributes, options)
+}
+
+/// What the search needs to know about one graph.
+struct Side<'a> {
+    adj: Adjacency<'a>,
+    /// Sorted keys of the links from one node to another.
+    between: HashMap<(usize, usize), Vec<String>>,
+    /// Degrees, self-loops and node key; matched nodes must agree on it.
+    signature: Vec<(usize, usize, usize, String)>,
+}
+
+impl<'a> Side<'a> {
+    fn new(graph: &'a GraphData, options: &IsomorphismOptions) -> Self {
+        let adj = Adjacency::new(graph);
+        let mut between: HashMap<(usize, usize), Vec<String>> = HashMap::new();
+        for (s, out) in adj.out.iter().enumerate() {
+            for &(t, l) in out {
+                between.entry((s, t)).or_default().push(link_key(&graph.links[l], options));
+            }
+        }
+        between.values_mut().for_each(|keys| keys.sort());
+
+        let signature = (0..adj.len())
+            .map(|v| {
+                let loops = between.get(&(v, v)).map_or(0, Vec::len);
+                (adj.out[v].len(), adj.inc[v].len(), loops, node_key(&graph.nodes[v], options))
+            })
+            .collect();
+        Side { adj, between, signature }
+    }
+
+    fn links(&self, s: usize, t: usize) -> &[String] {
+        self.between.get(&(s, t)).map_or(&[], Vec::as_slice)
+    }
+}
+
+struct Search<'s, 'a> {
+    g1: &'s Side<'a>,
+    g2: &'s Side<'a>,
+    order: Vec<usize>,
+    map12: Vec<Option<usize>>,
+    map21: Vec<Option<usize>>,
+    steps: usize,
+    max_steps: usize,
+}
+
+impl Search<'_, '_> {
+    /// Whether `v1 -> v2` is consistent with the pairs mapped so far.
+    fn feasible(&self, v1: usize, v2: usize) -> bool {
+        let (g1, g2) = (self.g1, self.g2);
+        if self.map21[v2].is_some() || g1.signature[v1] != g2.signature[v2] || g1.links(v1, v1) != g2.links(v2, v2) {
+            return false;
+        }
+        let consistent = |u1: usize, u2: usize| {
+            g1.links(v1, u1) == g2.links(v2, u2) && g1.links(u1, v1) == g2.links(u2, v2)
+        };
+        g1.adj.neighbors(v1).all(|u1| self.map12[u1].is_none_or(|u2| consistent(u1, u2)))
+            && g2.adj.neighbors(v2).all(|u2| self.map21[u2].is_none_or(|u1| consistent(u1, u2)))
+    }
+
+    /// Candidates for `v1`: the neighbours of a mapped neighbour's image, or
+    /// every node when nothing around it is mapped yet.
+    fn candidates(&self, v1: usize) -> Vec<usize> {
+        match self.g1.adj.neighbors(v1).find_map(|u1| self.map12[u1]) {
+            Some(u2) => {
+                let mut c: Vec<usize> = self.g2.adj.neighbors(u2).collect();
+                c.sort_unstable();
+                c.dedup();
+                c
+            }
+            None => (0..self.g2.adj.len()).collect(),
+        }
+    }
+
+    /// Backtracking over `order`, with an explicit stack of frames (one per
+    /// matched depth) so that long chains don't overflow the call stack.
+    fn run(&mut self) -> Result<bool, String> {
+        if self.order.is_empty() {
+            return Ok(true);
+        }
+        let mut frames = vec![Frame { candidates: self.candidates(self.order[0]), next: 0, matched: None }];
+        while !frames.is_empty() {
+            let depth = frames.len() - 1;
+            let frame = &mut frames[depth];
+            let v1 = self.order[depth];
+            // Coming back to this depth: undo the pair that failed deeper down.
+            if let Some(v2) = frame.matched.take() {
+                self.map12[v1] = None;
+                self.map21[v2] = None;
+            }
+
+            let mut found = None;
+            while let Some(&v2) = frame.candidates.get(frame.next) {
+                frame.next += 1;
+                self.steps += 1;
+                if self.steps > self.max_steps {
+                    return Err(format!("Isomorphism search gave up after {} steps", self.max_steps));
+                }
+                if self.feasible(v1, v2) {
+                    found = Some(v2);
+                    break;
+                }
+            }
+
+            match found {
+                Some(v2) => {
+                    frame.matched = Some(v2);
+                    self.map12[v1] = Some(v2);
+                    self.map21[v2] = Some(v1);
+                    if depth + 1 == self.order.len() {
+                        return Ok(true);
+                    }
+                    let candidates = self.candidates(self.order[depth + 1]);
+                    frames.push(Frame { candidates, next: 0, matched: None });
+                }
+                None => {
+                    frames.pop();
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Search state at one depth: the candidates for that node of `order`, the
+/// next one to try and the one currently matched.
+struct Frame {
+    candidates: Vec<usize>,
+    next: usize,
+    matched: Option<usize>,
+}
+
+/// Matching order: breadth-first from the highest-degree node of each
+/// component, so most nodes have a mapped neighbour when they are reached.
+fn matching_order(side: &Side) -> Vec<usize> {
+    let n = side.adj.len();
+    let degree = |v: usize| side.adj.out[v].len() + side.adj.inc[v].len();
+    let mut by_degree: Vec<usize> = (0..n).collect();
+    by_degree.sort_by(|&a, &b| degree(b).cmp(&degree(a)).then(side.adj.ids[a].cmp(side.adj.ids[b])));
+
+    let mut seen = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+    for &root in &by_degree {
+        if seen[root] {
+            continue;
+        }
+        seen[root] = true;
+        let start = order.len();
+        order.push(root);
+        let mut i = start;
+        while i < order.len() {
+            let v = order[i];
+            let mut next: Vec<usize> = side.adj.neighbors(v).filter(|&w| !seen[w]).collect();
+            next.sort_by(|&a, &b| degree(b).cmp(&degree(a)).then(a.cmp(&b)));
+            next.dedup();
+            for w in next {
+                seen[w] = true;
+                order.push(w);
+            }
+            i += 1;
+        }
+    }
+    order
+}
+
+/// Find a mapping from the nodes of `g1` onto those of `g2` that preserves
+/// edges (with multiplicity and direction) and, if requested, labels or
+/// attributes. `Ok(None)` when the graphs are not isomorphic; an error when
+/// the search exceeds `max_steps`.
+pub fn find_isomorphism(g1: &GraphData, g2: &GraphData, options: &IsomorphismOptions)
+    -> Result<Option<NodeMapping>, String>
+{
+    let s1 = Side::new(g1, options);
+    let s2 = Side::new(g2, options);
+    if s1.adj.len() != s2.adj.len() {
+        return Ok(None);
+    }
+    let mut sig1 = s1.signature.clone();
+    let mut sig2 = s2.signature.clone();
+    sig1.sort();
+    sig2.sort();
+    let mut links1: Vec<&Vec<String>> = s1.between.values().collect();
+    let mut links2: Vec<&Vec<String>> = s2.between.values().collect();
+    links1.sort();
+    links2.sort();
+    if sig1 != sig2 || links1 != links2 {
+        return Ok(None);
+    }
+
+    let mut search = Search {
+        g1: &s1,
+        g2: &s2,
+        order: matching_order(&s1),
+        map12: vec![None; s1.adj.len()],
+        map21: vec![None; s2.adj.len()],
+        steps: 0,
+        max_steps: options.max_steps,
+    };
+    if !search.run()? {
+        return Ok(None);
+    }
+
+    Ok(Some(search.map12.iter().enumerate()
+        .filter_map(|(v1, v2)| v2.map(|v2| (s1.adj.ids[v1].to_string(), s2.adj.ids[v2].to_string())))
+        .collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{compute_delta, compute_delta_mapped, parse_graph};
+
+    fn chain(prefix: &str, n: usize) -> GraphData {
+        let mut dot = String::from("digraph {\n");
+        for i in 0..n {
+            dot.push_str(&format!("{p}{i} -> {p}{j};\n", p = prefix, i = i, j = i + 1));
+        }
+        dot.push('}');
+        parse_graph(&dot).unwrap()
+    }
+
+    #[test]
+    fn renamed_graphs_are_isomorphic() {
+        let g1 = parse_graph("digraph { a -> b; b -> c; c -> a; c -> d }").unwrap();
+        let g2 = parse_graph("digraph { z -> x; x -> y; y -> z; z -> w }").unwrap();
+        let mapping = find_isomorphism(&g1, &g2, &IsomorphismOptions::default()).unwrap().unwrap();
+        assert_eq!(mapping["a"], "x");
+        assert_eq!(mapping["b"], "y");
+        assert_eq!(mapping["c"], "z");
+        assert_eq!(mapping["d"], "w");
+    }
+
+    #[test]
+    fn direction_and_labels_matter() {
+        let g1 = parse_graph("digraph { a -> b; b -> c }").unwrap();
+        let g2 = parse_graph("digraph { a -> b; c -> b }").unwrap();
+        assert!(find_isomorphism(&g1, &g2, &IsomorphismOptions::default()).unwrap().is_none());
+
+        let g1 = parse_graph("digraph { a [label=x]; a -> b }").unwrap();
+        let g2 = parse_graph("digraph { c [label=y]; c -> d }").unwrap();
+        let labels = IsomorphismOptions { match_labels: true, ..IsomorphismOptions::default() };
+        assert!(find_isomorphism(&g1, &g2, &IsomorphismOptions::default()).unwrap().is_some());
+        assert!(find_isomorphism(&g1, &g2, &labels).unwrap().is_none());
+    }
+
+    #[test]
+    fn structural_delta_ignores_renames() {
+        let g1 = parse_graph("digraph { subgraph cluster_a { a } a -> b; b -> c }").unwrap();
+        let g2 = parse_graph("digraph { subgraph cluster_a { p } p -> q; q -> r }").unwrap();
+        let mapping = find_isomorphism(&g1, &g2, &IsomorphismOptions::default()).unwrap().unwrap();
+        let delta = compute_delta_mapped(&g1, &g2, &mapping);
+        assert!(delta.added_nodes.is_empty() && delta.removed_nodes.is_empty());
+        assert!(delta.added_edges.is_empty() && delta.removed_edges.is_empty());
+        assert!(delta.moved_nodes.is_empty());
+        assert_eq!(compute_delta(&g1, &g2).removed_nodes, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn long_chains_do_not_overflow_the_stack() {
+        let (g1, g2) = (chain("a", 60_000), chain("b", 60_000));
+        let mapping = find_isomorphism(&g1, &g2, &IsomorphismOptions::default()).unwrap().unwrap();
+        assert_eq!(mapping.len(), 60_001);
+        assert_eq!(mapping["a0"], "b0");
+        assert_eq!(mapping["a60000"], "b60000");
+    }
+}
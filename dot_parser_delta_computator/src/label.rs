@@ -0,0 +1,433 @@
+//! Decoded DOT labels: escape sequences (`\n`, `\l`, `\r`, `\N`, `\G`, `\E`,
+//! `\T`, `\H`) in plain labels, and the rich text of HTML-like `<...>` labels.
+
+use crate::graph::{unquote, GraphData, GraphLink, GraphNode};
+use serde::{Serialize, Deserialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Justify {
+    Center,
+    Left,
+    Right,
+}
+
+/// Font settings of an HTML label run; plain labels use the default.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TextStyle {
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub bold: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub italic: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub underline: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub strike: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub subscript: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub superscript: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub face: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub point_size: Option<f64>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TextRun {
+    pub text: String,
+    #[serde(flatten)]
+    pub style: TextStyle,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LabelLine {
+    pub justify: Justify,
+    pub runs: Vec<TextRun>,
+}
+
+impl LabelLine {
+    pub fn text(&self) -> String {
+        self.runs.iter().map(|r| r.text.as_str()).collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Label {
+    /// Whether the label was HTML-like (`label=<...>`).
+    pub html: bool,
+    pub lines: Vec<LabelLine>,
+}
+
+impl Label {
+    /// The label as plain text, one line per `\n`.
+    pub fn text(&self) -> String {
+        self.lines.iter().map(LabelLine::text).collect::<Vec<_>>().join("\n")
+    }
+}
+
+/// Names the `\N`, `\G`, `\E`, `\T` and `\H` escapes stand for; escapes
+/// without a name in the context (like `\N` in an edge label) are kept.
+#[derive(Clone, Copy, Default)]
+pub struct LabelContext<'a> {
+    pub graph: Option<&'a str>,
+    pub node: Option<&'a str>,
+    pub tail: Option<&'a str>,
+    pub head: Option<&'a str>,
+    pub directed: bool,
+}
+
+fn plain_line(text: String, justify: Justify) -> LabelLine {
+    LabelLine { justify, runs: vec![TextRun { text, style: TextStyle::default() }] }
+}
+
+/// Decode a plain label. A `\n`, `\l` or `\r` ends a centered, left- or
+/// right-justified line; text after the last one forms a centered line.
+fn decode_text(raw: &str, context: &LabelContext) -> Label {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            line.push(c);
+            continue;
+        }
+        let escape = match chars.next() {
+            Some(e) => e,
+            None => {
+                line.push('\\');
+                break;
+            }
+        };
+        let name = match escape {
+            'n' | 'l' | 'r' => {
+                let justify = match escape {
+                    'l' => Justify::Left,
+                    'r' => Justify::Right,
+                    _ => Justify::Center,
+                };
+                lines.push(plain_line(std::mem::take(&mut line), justify));
+                continue;
+            }
+            'N' => context.node.map(unquote),
+            'G' => context.graph.map(unquote),
+            'T' => context.tail.map(unquote),
+            'H' => context.head.map(unquote),
+            'E' => match (context.tail, context.head) {
+                (Some(t), Some(h)) => {
+                    Some(format!("{}{}{}", unquote(t), if context.directed { "->" } else { "--" }, unquote(h)))
+                }
+                _ => None,
+            },
+            other => Some(other.to_string()),
+        };
+        match name {
+            Some(name) => line.push_str(&name),
+            None => {
+                line.push('\\');
+                line.push(escape);
+            }
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(plain_line(line, Justify::Center));
+    }
+    Label { html: false, lines }
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                    u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32)
+                }
+                _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+                _ => None,
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Name (upper-cased), closing flag and attributes of the tag `<...>` body.
+fn parse_tag(body: &str) -> (String, bool, Vec<(String, String)>) {
+    let body = body.trim().trim_end_matches('/');
+    let (closing, body) = match body.strip_prefix('/') {
+        Some(rest) => (true, rest),
+        None => (false, body),
+    };
+    let name_end = body.find(char::is_whitespace).unwrap_or(body.len());
+    let name = body[..name_end].to_ascii_uppercase();
+
+    let mut attributes = Vec::new();
+    let mut rest = body[name_end..].trim_start();
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].trim().to_ascii_lowercase();
+        let value = rest[eq + 1..].trim_start();
+        let (value, next) = match value.chars().next() {
+            Some(q) if q == '"' || q == '\'' => {
+                let end = value[1..].find(q).map_or(value.len(), |e| e + 1);
+                (&value[1..end], value.get(end + 1..).unwrap_or(""))
+            }
+            _ => {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+        };
+        attributes.push((key, decode_entities(value)));
+        rest = next.trim_start();
+    }
+    (name, closing, attributes)
+}
+
+fn justify_of(attributes: &[(String, String)]) -> Option<Justify> {
+    attributes.iter().find(|(k, _)| k == "align").map(|(_, v)| match v.to_ascii_uppercase().as_str() {
+        "LEFT" => Justify::Left,
+        "RIGHT" => Justify::Right,
+        _ => Justify::Center,
+    })
+}
+
+/// Text formatting tags, which don't break the text around them.
+const INLINE_TAGS: &[&str] = &["B", "I", "U", "S", "SUB", "SUP", "O", "FONT"];
+
+/// Decode an HTML-like label (without its outer `<` `>`). Text formatting
+/// tags become run styles; `<BR/>` and table rows end lines. Other table
+/// structure is flattened. Whitespace-only text is kept between runs of a
+/// line (`<B>a</B> <I>b</I>`) and dropped at line and cell boundaries.
+fn decode_html(raw: &str) -> Label {
+    let mut lines = Vec::new();
+    let mut runs: Vec<TextRun> = Vec::new();
+    // Whitespace after the last run, kept if more text follows on the line.
+    let mut space: Option<TextRun> = None;
+    // Style in effect, and the tag that pushed each saved style.
+    let mut style = TextStyle::default();
+    let mut saved: Vec<(String, TextStyle)> = Vec::new();
+    // Default justification of lines, from the innermost cell's `ALIGN`.
+    let mut cell_justify = vec![Justify::Center];
+
+    let mut end_line = |runs: &mut Vec<TextRun>, justify: Justify, force: bool| {
+        if force || !runs.is_empty() {
+            lines.push(LabelLine { justify, runs: std::mem::take(runs) });
+        }
+    };
+
+    let mut rest = raw;
+    loop {
+        let start = rest.find('<').unwrap_or(rest.len());
+        let text = decode_entities(&rest[..start].replace(['\n', '\r', '\t'], " "));
+        if !text.trim().is_empty() {
+            runs.extend(space.take());
+            runs.push(TextRun { text, style: style.clone() });
+        } else if !text.is_empty() && !runs.is_empty() {
+            space = Some(TextRun { text, style: style.clone() });
+        }
+        if start == rest.len() {
+            break;
+        }
+        let after = &rest[start + 1..];
+        let end = after.find('>').unwrap_or(after.len());
+        let tag = &after[..end];
+        rest = after.get(end + 1..).unwrap_or("");
+        if tag.starts_with('!') {
+            continue;
+        }
+
+        let (name, closing, attributes) = parse_tag(tag);
+        if !INLINE_TAGS.contains(&name.as_str()) {
+            space = None;
+        }
+        let justify = *cell_justify.last().unwrap_or(&Justify::Center);
+        match (name.as_str(), closing) {
+            ("BR", _) => end_line(&mut runs, justify_of(&attributes).unwrap_or(justify), true),
+            ("TR", true) => end_line(&mut runs, justify, false),
+            ("TD", false) => {
+                if !runs.is_empty() {
+                    runs.push(TextRun { text: " ".to_string(), style: style.clone() });
+                }
+                cell_justify.push(justify_of(&attributes).unwrap_or(justify));
+            }
+            ("TD", true) if cell_justify.len() > 1 => {
+                cell_justify.pop();
+            }
+            ("B" | "I" | "U" | "S" | "SUB" | "SUP" | "O" | "FONT", false) => {
+                saved.push((name.clone(), style.clone()));
+                match name.as_str() {
+                    "B" => style.bold = true,
+                    "I" => style.italic = true,
+                    "U" => style.underline = true,
+                    "S" => style.strike = true,
+                    "SUB" => style.subscript = true,
+                    "SUP" => style.superscript = true,
+                    "FONT" => {
+                        for (k, v) in &attributes {
+                            match k.as_str() {
+                                "color" => style.color = Some(v.clone()),
+                                "face" => style.face = Some(v.clone()),
+                                "point-size" => style.point_size = v.parse().ok(),
+                                _ => {}
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            (_, true) => {
+                if let Some(i) = saved.iter().rposition(|(n, _)| *n == name) {
+                    style = saved[i].1.clone();
+                    saved.truncate(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    let justify = *cell_justify.last().unwrap_or(&Justify::Center);
+    end_line(&mut runs, justify, false);
+    if lines.is_empty() {
+        lines.push(plain_line(String::new(), Justify::Center));
+    }
+    Label { html: true, lines }
+}
+
+/// Decode a raw label value. `html` says whether it was an HTML string
+/// (`label=<...>`); quoted text is never decoded as HTML, even in `<...>`.
+pub fn decode_label(raw: &str, html: bool, context: &LabelContext) -> Label {
+    if html {
+        let inner = raw.strip_prefix('<').and_then(|r| r.strip_suffix('>')).unwrap_or(raw);
+        decode_html(inner)
+    } else {
+        decode_text(raw, context)
+    }
+}
+
+/// A node's label as displayed: its `label` attribute, or its name (`\N`).
+pub fn node_label(node: &GraphNode, graph_name: Option<&str>) -> Label {
+    let raw = node.attributes.get("label").map_or("\\N", String::as_str);
+    let html = node.attributes.contains_key("label") && node.html_attributes.contains("label");
+    decode_label(raw, html, &LabelContext { graph: graph_name, node: Some(&node.id), ..LabelContext::default() })
+}
+
+/// A link's label as displayed, if it has one.
+pub fn link_label(link: &GraphLink, graph_name: Option<&str>, directed: bool) -> Option<Label> {
+    let context = LabelContext {
+        graph: graph_name,
+        tail: Some(&link.source),
+        head: Some(&link.target),
+        directed,
+        ..LabelContext::default()
+    };
+    let html = link.html_attributes.contains("label");
+    link.attributes.get("label").map(|raw| decode_label(raw, html, &context))
+}
+
+/// Set `display_label` on every node and link of `graph`.
+pub fn decode_labels(graph: &mut GraphData, graph_name: Option<&str>) {
+    let directed = graph.directed;
+    for node in &mut graph.nodes {
+        node.display_label = Some(node_label(node, graph_name));
+    }
+    for link in &mut graph.links {
+        link.display_label = link_label(link, graph_name, directed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(label: &Label) -> Vec<(String, Justify)> {
+        label.lines.iter().map(|l| (l.text(), l.justify)).collect()
+    }
+
+    #[test]
+    fn line_escapes_set_justification() {
+        let label = decode_label("left\\lright\\rmid\\nend", false, &LabelContext::default());
+        assert!(!label.html);
+        assert_eq!(lines(&label), vec![
+            ("left".to_string(), Justify::Left),
+            ("right".to_string(), Justify::Right),
+            ("mid".to_string(), Justify::Center),
+            ("end".to_string(), Justify::Center),
+        ]);
+        assert_eq!(lines(&decode_label("", false, &LabelContext::default())), vec![(String::new(), Justify::Center)]);
+    }
+
+    #[test]
+    fn name_escapes_use_the_context() {
+        let node = LabelContext { graph: Some("G"), node: Some("\"a b\""), ..LabelContext::default() };
+        assert_eq!(decode_label("\\N in \\G", false, &node).text(), "a b in G");
+        let edge = LabelContext { tail: Some("t"), head: Some("h"), directed: true, ..LabelContext::default() };
+        assert_eq!(decode_label("\\E \\T \\H \\N", false, &edge).text(), "t->h t h \\N");
+        let undirected = LabelContext { directed: false, ..edge };
+        assert_eq!(decode_label("\\E", false, &undirected).text(), "t--h");
+    }
+
+    #[test]
+    fn html_labels_become_styled_runs() {
+        let raw = "<<B>bold</B> &amp; <I>it</I><BR ALIGN=\"LEFT\"/><FONT COLOR=\"red\" POINT-SIZE=\"9\">x</FONT>>";
+        let label = decode_label(raw, true, &LabelContext::default());
+        assert!(label.html);
+        assert_eq!(lines(&label), vec![("bold & it".to_string(), Justify::Left), ("x".to_string(), Justify::Center)]);
+        let runs = &label.lines[0].runs;
+        assert!(runs[0].style.bold && !runs[0].style.italic);
+        assert_eq!(runs[1].style, TextStyle::default());
+        assert!(runs[2].style.italic && !runs[2].style.bold);
+        let font = &label.lines[1].runs[0].style;
+        assert_eq!((font.color.as_deref(), font.point_size), (Some("red"), Some(9.0)));
+    }
+
+    #[test]
+    fn whitespace_between_runs_is_kept() {
+        let label = decode_label("<<B>a</B> <I>b</I>>", true, &LabelContext::default());
+        assert_eq!(label.text(), "a b");
+        assert_eq!(label.lines[0].runs.len(), 3);
+        let label = decode_label("<<B>a</B>  <BR/> <I>b</I> \n <FONT>c</FONT> >", true, &LabelContext::default());
+        assert_eq!(lines(&label), vec![("a".to_string(), Justify::Center), ("b   c".to_string(), Justify::Center)]);
+        let raw = "<<TABLE> <TR> <TD> <B>a</B> </TD> <TD>b</TD> </TR>\n<TR><TD>c</TD></TR> </TABLE>>";
+        assert_eq!(decode_label(raw, true, &LabelContext::default()).text(), "a b\nc");
+    }
+
+    #[test]
+    fn table_rows_break_lines() {
+        let raw = "<<TABLE><TR><TD>a</TD><TD>b</TD></TR><TR><TD>c</TD></TR></TABLE>>";
+        let label = decode_label(raw, true, &LabelContext::default());
+        assert_eq!(label.text(), "a b\nc");
+    }
+
+    #[test]
+    fn quoted_text_in_angle_brackets_is_not_html() {
+        let label = decode_label("<init>", false, &LabelContext::default());
+        assert!(!label.html);
+        assert_eq!(label.text(), "<init>");
+    }
+}
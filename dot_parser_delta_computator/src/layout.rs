@@ -0,0 +1,407 @@
+//! Force-directed 3D layout (springs on links, many-body repulsion, weak
+//! gravity), with the same cooling schedule as d3-force. Repulsion uses a
+//! Barnes–Hut octree, so a step costs O(n log n) rather than O(n²). Nodes are processed
+//! in id order and seeded from their ids, so a given graph and seed yield the
+//! same positions wherever the layout runs.
+
+use crate::canonical::fnv1a;
+use crate::graph::{Adjacency, GraphData};
+use crate::rng::SplitMix64;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LayoutOptions {
+    pub seed: u64,
+    /// Rest length of a link.
+    pub link_distance: f32,
+    /// Many-body strength; negative values repel.
+    pub charge: f32,
+    /// Pull towards the origin.
+    pub gravity: f32,
+    /// Fraction of velocity lost per step.
+    pub velocity_decay: f32,
+    /// The layout has converged once its temperature drops below this.
+    pub alpha_min: f32,
+    /// Barnes–Hut accuracy: a group of nodes whose cell is smaller than
+    /// `theta` times its distance repels as one body. 0 sums every pair.
+    #[serde(default = "default_theta")]
+    pub theta: f32,
+}
+
+fn default_theta() -> f32 {
+    0.9
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        LayoutOptions {
+            seed: 0,
+            link_distance: 30.0,
+            charge: -30.0,
+            gravity: 0.01,
+            velocity_decay: 0.4,
+            alpha_min: 0.001,
+            theta: default_theta(),
+        }
+    }
+}
+
+pub struct ForceLayout {
+    options: LayoutOptions,
+    index: HashMap<String, usize>,
+    /// Position in `graph.nodes` of each internal (id-sorted) node.
+    order: Vec<usize>,
+    /// (source, target, strength, bias towards source)
+    springs: Vec<(usize, usize, f32, f32)>,
+    positions: Vec<[f32; 3]>,
+    velocities: Vec<[f32; 3]>,
+    pinned: Vec<Option<[f32; 3]>>,
+    alpha: f32,
+    alpha_decay: f32,
+}
+
+impl ForceLayout {
+    pub fn new(graph: &GraphData, options: LayoutOptions) -> Self {
+        let adj = Adjacency::new(graph);
+        let mut order: Vec<usize> = (0..adj.len()).collect();
+        order.sort_by_key(|&n| adj.ids[n]);
+        let mut rank = vec![0; adj.len()];
+        for (r, &n) in order.iter().enumerate() {
+            rank[n] = r;
+        }
+
+        let ids: Vec<String> = order.iter().map(|&n| adj.ids[n].to_string()).collect();
+        let index = ids.iter().enumerate().map(|(i, id)| (id.clone(), i)).collect();
+        let degree: Vec<usize> = order.iter().map(|&n| adj.out[n].len() + adj.inc[n].len()).collect();
+
+        let mut springs = Vec::new();
+        for (s, out) in adj.out.iter().enumerate() {
+            for &(t, l) in out {
+                let (a, b) = (rank[s], rank[t]);
+                if a == b {
+                    continue;
+                }
+                let weight = graph.links[l].weight
+                    .map(|w| w as f32)
+                    .filter(|w| *w > 0.0 && w.is_finite())
+                    .unwrap_or(1.0);
+                let strength = weight / degree[a].min(degree[b]) as f32;
+                let bias = degree[a] as f32 / (degree[a] + degree[b]) as f32;
+                springs.push((a, b, strength, bias));
+            }
+        }
+        springs.sort_by_key(|x| (x.0, x.1));
+
+        // Start each node on a sphere, at a position derived from its id.
+        let radius = options.link_distance * (ids.len() as f32).cbrt().max(1.0);
+        let positions = ids.iter()
+            .map(|id| {
+                let mut rng = SplitMix64::new(fnv1a(id) ^ options.seed);
+                let z = 2.0 * rng.next_f64() - 1.0;
+                let theta = std::f64::consts::TAU * rng.next_f64();
+                let r = (1.0 - z * z).sqrt();
+                let scale = radius * rng.next_f64().cbrt() as f32;
+                [(r * theta.cos()) as f32 * scale, (r * theta.sin()) as f32 * scale, z as f32 * scale]
+            })
+            .collect();
+
+        let n = ids.len();
+        let alpha_decay = 1.0 - options.alpha_min.powf(1.0 / 300.0);
+        ForceLayout {
+            options,
+            index,
+            order,
+            springs,
+            positions,
+            velocities: vec![[0.0; 3]; n],
+            pinned: vec![None; n],
+            alpha: 1.0,
+            alpha_decay,
+        }
+    }
+
+    /// Advance the simulation by one tick; returns the largest node displacement.
+    pub fn step(&mut self) -> f32 {
+        self.alpha -= self.alpha * self.alpha_decay;
+        let alpha = self.alpha;
+        let (p, v) = (&self.positions, &mut self.velocities);
+
+        for &(s, t, strength, bias) in &self.springs {
+            let mut d = [0.0; 3];
+            for k in 0..3 {
+                d[k] = p[t][k] + v[t][k] - p[s][k] - v[s][k];
+            }
+            let len = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt().max(1e-6);
+            let f = (len - self.options.link_distance) / len * alpha * strength;
+            for k in 0..3 {
+                v[t][k] -= d[k] * f * bias;
+                v[s][k] += d[k] * f * (1.0 - bias);
+            }
+        }
+
+        let n = p.len();
+        let tree = Octree::new(p);
+        for (i, vi) in v.iter_mut().enumerate() {
+            let push = tree.repulsion(p, i, self.options.charge * alpha, self.options.theta);
+            for k in 0..3 {
+                vi[k] += push[k];
+            }
+        }
+
+        let mut moved: f32 = 0.0;
+        let keep = 1.0 - self.options.velocity_decay;
+        for i in 0..n {
+            if let Some(pin) = self.pinned[i] {
+                self.positions[i] = pin;
+                self.velocities[i] = [0.0; 3];
+                continue;
+            }
+            let mut shift: f32 = 0.0;
+            for k in 0..3 {
+                self.velocities[i][k] -= self.positions[i][k] * self.options.gravity * alpha;
+                self.velocities[i][k] *= keep;
+                self.positions[i][k] += self.velocities[i][k];
+                shift += self.velocities[i][k] * self.velocities[i][k];
+            }
+            moved = moved.max(shift.sqrt());
+        }
+        moved
+    }
+
+    /// Step until the layout cools down or `max_steps` is reached; returns the steps taken.
+    pub fn run_until_converged(&mut self, max_steps: usize) -> usize {
+        let mut steps = 0;
+        while steps < max_steps && !self.converged() {
+            self.step();
+            steps += 1;
+        }
+        steps
+    }
+
+    pub fn converged(&self) -> bool {
+        self.alpha < self.options.alpha_min
+    }
+
+    /// Restart cooling from `alpha`, e.g. after pinning nodes or a graph change.
+    pub fn reheat(&mut self, alpha: f32) {
+        self.alpha = alpha;
+    }
+
+    /// Fix a node at a position until `unpin` is called.
+    pub fn pin(&mut self, id: &str, position: [f32; 3]) -> Result<(), String> {
+        let i = self.find(id)?;
+        self.pinned[i] = Some(position);
+        self.positions[i] = position;
+        Ok(())
+    }
+
+    pub fn unpin(&mut self, id: &str) -> Result<(), String> {
+        let i = self.find(id)?;
+        self.pinned[i] = None;
+        Ok(())
+    }
+
+    fn find(&self, id: &str) -> Result<usize, String> {
+        self.index.get(id).copied().ok_or_else(|| format!("Unknown node: {}", id))
+    }
+
+    /// Position of a node, by id.
+    pub fn position(&self, id: &str) -> Option<[f32; 3]> {
+        self.index.get(id).map(|&i| self.positions[i])
+    }
+
+    /// Positions in the order of the graph's nodes.
+    pub fn positions(&self) -> Vec<[f32; 3]> {
+        let mut out = vec![[0.0; 3]; self.positions.len()];
+        for (i, &n) in self.order.iter().enumerate() {
+            out[n] = self.positions[i];
+        }
+        out
+    }
+
+    /// Positions flattened as `x0, y0, z0, x1, ...`, in the order of the graph's nodes.
+    pub fn flat_positions(&self) -> Vec<f32> {
+        self.positions().into_iter().flat_map(|p| p.to_vec()).collect()
+    }
+}
+
+/// Most levels of the octree; deeper cells (coincident nodes) stay leaves.
+const MAX_DEPTH: usize = 16;
+
+struct Cell {
+    center: [f32; 3],
+    half: f32,
+    /// Number of nodes in the cell, and their centroid.
+    mass: f32,
+    centroid: [f32; 3],
+    children: Vec<usize>,
+    /// Nodes of a leaf.
+    nodes: Vec<usize>,
+}
+
+/// Barnes–Hut octree over the node positions; cell 0 is the root.
+struct Octree {
+    cells: Vec<Cell>,
+}
+
+impl Octree {
+    fn new(p: &[[f32; 3]]) -> Self {
+        let mut tree = Octree { cells: Vec::new() };
+        if p.is_empty() {
+            return tree;
+        }
+        let mut lo = p[0];
+        let mut hi = p[0];
+        for q in p {
+            for k in 0..3 {
+                lo[k] = lo[k].min(q[k]);
+                hi[k] = hi[k].max(q[k]);
+            }
+        }
+        let center = [(lo[0] + hi[0]) / 2.0, (lo[1] + hi[1]) / 2.0, (lo[2] + hi[2]) / 2.0];
+        let half = (0..3).map(|k| hi[k] - lo[k]).fold(0.0, f32::max) / 2.0 + 1e-3;
+        tree.build(p, (0..p.len()).collect(), center, half, 0);
+        tree
+    }
+
+    /// Add the cell holding `nodes`, and its subtree; returns its index.
+    fn build(&mut self, p: &[[f32; 3]], nodes: Vec<usize>, center: [f32; 3], half: f32, depth: usize) -> usize {
+        let mut centroid = [0.0; 3];
+        for &i in &nodes {
+            for k in 0..3 {
+                centroid[k] += p[i][k];
+            }
+        }
+        let mass = nodes.len() as f32;
+        for c in &mut centroid {
+            *c /= mass;
+        }
+        let index = self.cells.len();
+        self.cells.push(Cell { center, half, mass, centroid, children: Vec::new(), nodes: Vec::new() });
+        if nodes.len() <= 1 || depth == MAX_DEPTH {
+            self.cells[index].nodes = nodes;
+            return index;
+        }
+
+        let mut octants: Vec<Vec<usize>> = vec![Vec::new(); 8];
+        for i in nodes {
+            let o = (0..3).filter(|&k| p[i][k] >= center[k]).map(|k| 1 << k).sum::<usize>();
+            octants[o].push(i);
+        }
+        for (o, octant) in octants.into_iter().enumerate() {
+            if octant.is_empty() {
+                continue;
+            }
+            let mut child = center;
+            for (k, c) in child.iter_mut().enumerate() {
+                *c += if o & (1 << k) != 0 { half / 2.0 } else { -half / 2.0 };
+            }
+            let c = self.build(p, octant, child, half / 2.0, depth + 1);
+            self.cells[index].children.push(c);
+        }
+        index
+    }
+
+    fn contains(&self, cell: &Cell, q: &[f32; 3]) -> bool {
+        (0..3).all(|k| (q[k] - cell.center[k]).abs() <= cell.half)
+    }
+
+    /// Call `body` with the position and mass of every body acting on node
+    /// `i`: single nodes, or the centroid of a cell (not containing `i`) that
+    /// is smaller than `theta` times its distance.
+    fn bodies(&self, p: &[[f32; 3]], i: usize, theta: f32, mut body: impl FnMut(&[f32; 3], f32)) {
+        let mut stack = if self.cells.is_empty() { Vec::new() } else { vec![0] };
+        while let Some(c) = stack.pop() {
+            let cell = &self.cells[c];
+            let size = 2.0 * cell.half;
+            let d2: f32 = (0..3).map(|k| (cell.centroid[k] - p[i][k]).powi(2)).sum();
+            if !self.contains(cell, &p[i]) && size * size < theta * theta * d2 {
+                body(&cell.centroid, cell.mass);
+            } else if cell.children.is_empty() {
+                for &j in cell.nodes.iter().filter(|&&j| j != i) {
+                    body(&p[j], 1.0);
+                }
+            } else {
+                stack.extend(&cell.children);
+            }
+        }
+    }
+
+    /// Velocity change of node `i` from all other nodes, each pushing with
+    /// `strength / distance` along the line between them.
+    fn repulsion(&self, p: &[[f32; 3]], i: usize, strength: f32, theta: f32) -> [f32; 3] {
+        let mut push = [0.0; 3];
+        self.bodies(p, i, theta, |target, mass| {
+            let mut d = [0.0; 3];
+            for k in 0..3 {
+                d[k] = target[k] - p[i][k];
+            }
+            let len2 = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).max(1.0);
+            for k in 0..3 {
+                push[k] += d[k] * strength * mass / len2;
+            }
+        });
+        push
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::parse_graph;
+
+    fn chain(n: usize) -> GraphData {
+        let mut dot = String::from("digraph {\n");
+        for i in 0..n {
+            dot.push_str(&format!("n{} -> n{};\n", i, i + 1));
+        }
+        dot.push('}');
+        parse_graph(&dot).unwrap()
+    }
+
+    #[test]
+    fn barnes_hut_is_close_to_the_pairwise_sum() {
+        let layout = ForceLayout::new(&chain(400), LayoutOptions::default());
+        let p = &layout.positions;
+        let tree = Octree::new(p);
+        let (mut error, mut total) = (0.0, 0.0);
+        for i in 0..p.len() {
+            let exact = tree.repulsion(p, i, -1.0, 0.0);
+            let approx = tree.repulsion(p, i, -1.0, 0.9);
+            error += (0..3).map(|k| (exact[k] - approx[k]).powi(2)).sum::<f32>().sqrt();
+            total += (0..3).map(|k| exact[k].powi(2)).sum::<f32>().sqrt();
+        }
+        assert!(error / total < 0.05, "relative error {}", error / total);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_layout() {
+        let graph = chain(50);
+        let run = |seed| {
+            let mut layout = ForceLayout::new(&graph, LayoutOptions { seed, ..LayoutOptions::default() });
+            layout.run_until_converged(100);
+            layout.flat_positions()
+        };
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+    }
+
+    #[test]
+    fn approximation_visits_far_fewer_bodies() {
+        let layout = ForceLayout::new(&chain(2000), LayoutOptions::default());
+        let p = &layout.positions;
+        let tree = Octree::new(p);
+        let count = |theta| {
+            let mut bodies = 0;
+            for i in 0..p.len() {
+                tree.bodies(p, i, theta, |_, _| bodies += 1);
+            }
+            bodies
+        };
+        let n = p.len();
+        assert_eq!(count(0.0), n * (n - 1));
+        let approximate = count(0.9);
+        assert!(approximate * 10 < n * (n - 1), "{} bodies for {} nodes", approximate, n);
+    }
+}
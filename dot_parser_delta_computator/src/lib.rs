@@ -1,10 +1,182 @@
+mod analytics;
+mod anonymize;
+mod canonical;
+mod centrality;
+mod color;
+mod community;
+mod compose;
 mod graph;
-pub use graph::{parse_graph, compute_delta, GraphData, GraphDelta};
+mod interned;
+mod label;
+mod isomorphism;
+mod layout;
+mod lint;
+mod patch;
+mod path;
+mod policy;
+mod query;
+mod render;
+mod rng;
+mod weight;
+pub use analytics::{
+    connected_components, diameter, find_cycles, graph_stats, is_dag, strongly_connected_components,
+    topological_order, GraphStats, DEFAULT_CYCLE_LIMIT,
+};
+pub use anonymize::{anonymize, AnonymizeOptions, Anonymized, DEFAULT_KEPT_ATTRIBUTES};
+pub use canonical::{canonicalize, fingerprint};
+pub use centrality::{betweenness, closeness, compute_centrality, eigenvector, pagerank};
+pub use color::{
+    assign_colors, fill_color, invalid_colors, parse_color, parse_color_list, resolve_colors, ColorStop, Rgba, COLOR_ATTRIBUTES,
+};
+pub use community::{detect_communities, Communities, LouvainOptions};
+pub use compose::{compose, parse_mapping, ComposeMapping, ComposePart, CrossLink, ORIGIN_ATTRIBUTE};
+pub use graph::{parse_graph, compute_delta, compute_delta_mapped, write_dot, ClusterMove, GraphCluster, GraphData, GraphDelta, GraphLink, GraphNode};
+pub use interned::{edge_endpoints, edge_key, IndexedDelta, IndexedGraph, Interner};
+pub use isomorphism::{find_isomorphism, IsomorphismOptions, NodeMapping};
+pub use label::{
+    decode_label, decode_labels, link_label, node_label, Justify, Label, LabelContext, LabelLine, TextRun, TextStyle,
+};
+pub use layout::{ForceLayout, LayoutOptions};
+pub use lint::{lint_dot, Diagnostic, LintOptions, Severity};
+pub use patch::{json_patch, PatchOp};
+pub use path::{
+    all_simple_paths, find_paths, shortest_path, weighted_shortest_path, PathReport, WeightedPath,
+    DEFAULT_PATH_LIMIT,
+};
+pub use policy::{check_policy, parse_policy, EdgeRule, Policy, Violation};
+pub use query::{parse_query, run_query, query_graph, CmpOp, Matcher, Query};
+pub use render::{diff_dot, diff_graph, render_svg};
+pub use weight::{assign_weights, link_weight, parse_weight, WeightOptions, DEFAULT_WEIGHT_ATTRIBUTES};
 
+use serde::Serialize;
 use wasm_bindgen::prelude::*;
-use serde_json;
+use wasm_bindgen::JsCast;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_TYPES: &'static str = r#"
+export interface GraphNode {
+  id: string;
+  name: string;
+  attributes: Record<string, string>;
+  html_attributes?: string[];
+  cluster?: number;
+  metrics?: Record<string, number>;
+  subgraph?: string;
+  display_label?: Label;
+  colors?: Record<string, Rgba>;
+}
+
+export interface GraphLink {
+  source: string;
+  target: string;
+  label: string | null;
+  attributes: Record<string, string>;
+  html_attributes?: string[];
+  weight?: number;
+  display_label?: Label;
+  colors?: Record<string, Rgba>;
+}
+
+export interface Rgba {
+  r: number;
+  g: number;
+  b: number;
+  a: number;
+}
+
+export interface TextRun {
+  text: string;
+  bold?: boolean;
+  italic?: boolean;
+  underline?: boolean;
+  strike?: boolean;
+  subscript?: boolean;
+  superscript?: boolean;
+  color?: string;
+  face?: string;
+  point_size?: number;
+}
+
+export interface LabelLine {
+  justify: "center" | "left" | "right";
+  runs: TextRun[];
+}
+
+export interface Label {
+  html: boolean;
+  lines: LabelLine[];
+}
+
+export interface GraphCluster {
+  id: string;
+  parent?: string;
+  attributes: Record<string, string>;
+  html_attributes?: string[];
+}
+
+export interface GraphData {
+  nodes: GraphNode[];
+  links: GraphLink[];
+  clusters: GraphCluster[];
+  directed: boolean;
+  attributes?: Record<string, string>;
+  html_attributes?: string[];
+}
+
+export interface ClusterMove {
+  node: string;
+  from: string | null;
+  to: string | null;
+}
+
+export interface GraphDelta {
+  added_nodes: string[];
+  removed_nodes: string[];
+  added_edges: [string, string][];
+  removed_edges: [string, string][];
+  moved_nodes: ClusterMove[];
+  added_clusters: string[];
+  removed_clusters: string[];
+}
+
+export interface WeightedPath {
+  cost: number;
+  path: string[];
+}
+
+export interface PathReport {
+  from: string;
+  to: string;
+  reachable: boolean;
+  shortest: string[] | null;
+  weighted: WeightedPath | null;
+  all_paths: string[][];
+}
+
+export type NodeMapping = Record<string, string>;
+"#;
 
 #[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "GraphData")]
+    pub type JsGraphData;
+    #[wasm_bindgen(typescript_type = "GraphDelta")]
+    pub type JsGraphDelta;
+    #[wasm_bindgen(typescript_type = "PathReport")]
+    pub type JsPathReport;
+    #[wasm_bindgen(typescript_type = "NodeMapping | null")]
+    pub type JsNodeMapping;
+}
+
+/// Convert to a plain JS object (maps become objects, as with `JSON.parse`).
+fn to_js<T: Serialize, J: JsCast>(value: &T) -> Result<J, JsValue> {
+    value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+}
+
+#[wasm_bindgen]
+#[derive(Default)]
 pub struct DotParser;
 
 #[wasm_bindgen]
@@ -15,18 +187,154 @@ impl DotParser {
     }
 
     #[wasm_bindgen]
-    pub fn parse(&self, dot: &str) -> Result<String, JsValue> {
+    pub fn parse(&self, dot: &str) -> Result<JsGraphData, JsValue> {
         let g = parse_graph(dot).map_err(|e| JsValue::from_str(&e))?;
-        serde_json::to_string(&g)
-            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+        to_js(&g)
+    }
+
+    /// Parse `dot`, taking link weights from the first valid attribute of
+    /// `attributes` (e.g. `["len", "weight"]`) instead of the default order.
+    #[wasm_bindgen]
+    pub fn parse_weighted(&self, dot: &str, attributes: Vec<String>) -> Result<JsGraphData, JsValue> {
+        let mut g = parse_graph(dot).map_err(|e| JsValue::from_str(&e))?;
+        assign_weights(&mut g, &WeightOptions { attributes });
+        to_js(&g)
     }
 
     #[wasm_bindgen]
-    pub fn delta(&self, dot1: &str, dot2: &str) -> Result<String, JsValue> {
+    pub fn delta(&self, dot1: &str, dot2: &str) -> Result<JsGraphDelta, JsValue> {
         let g1 = parse_graph(dot1).map_err(|e| JsValue::from_str(&e))?;
         let g2 = parse_graph(dot2).map_err(|e| JsValue::from_str(&e))?;
         let d = compute_delta(&g1, &g2);
-        serde_json::to_string(&d)
-            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+        to_js(&d)
+    }
+
+    #[wasm_bindgen]
+    pub fn path(&self, dot: &str, from: &str, to: &str, limit: usize) -> Result<JsPathReport, JsValue> {
+        let g = parse_graph(dot).map_err(|e| JsValue::from_str(&e))?;
+        let report = find_paths(&g, from, to, limit).map_err(|e| JsValue::from_str(&e))?;
+        to_js(&report)
+    }
+
+    /// Parse `dot` and run Louvain on it, with `cluster` set on each node.
+    #[wasm_bindgen]
+    pub fn cluster(&self, dot: &str, seed: u64, min_cluster_size: usize) -> Result<JsGraphData, JsValue> {
+        let mut g = parse_graph(dot).map_err(|e| JsValue::from_str(&e))?;
+        let options = LouvainOptions { seed, min_cluster_size, ..LouvainOptions::default() };
+        detect_communities(&mut g, &options);
+        to_js(&g)
+    }
+
+    /// Node mapping from `dot1` onto `dot2` if the graphs are isomorphic,
+    /// otherwise `null`.
+    #[wasm_bindgen]
+    pub fn isomorphism(&self, dot1: &str, dot2: &str, match_labels: bool, match_attributes: bool) -> Result<JsNodeMapping, JsValue> {
+        let g1 = parse_graph(dot1).map_err(|e| JsValue::from_str(&e))?;
+        let g2 = parse_graph(dot2).map_err(|e| JsValue::from_str(&e))?;
+        let options = IsomorphismOptions { match_labels, match_attributes, ..IsomorphismOptions::default() };
+        let mapping = find_isomorphism(&g1, &g2, &options).map_err(|e| JsValue::from_str(&e))?;
+        to_js(&mapping)
+    }
+
+    /// Stable hash of the graph's canonical form; unchanged when only
+    /// statement order, whitespace or quoting differ.
+    #[wasm_bindgen]
+    pub fn fingerprint(&self, dot: &str) -> Result<String, JsValue> {
+        let g = parse_graph(dot).map_err(|e| JsValue::from_str(&e))?;
+        Ok(fingerprint(&g))
+    }
+
+    /// Parse `dot`, with centralities in each node's `metrics`.
+    #[wasm_bindgen]
+    pub fn centrality(&self, dot: &str) -> Result<JsGraphData, JsValue> {
+        let mut g = parse_graph(dot).map_err(|e| JsValue::from_str(&e))?;
+        compute_centrality(&mut g);
+        to_js(&g)
+    }
+}
+
+/// Keeps the last parsed graph so each snapshot is parsed only once.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct DotSession {
+    current: Option<GraphData>,
+}
+
+#[wasm_bindgen]
+impl DotSession {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        DotSession { current: None }
+    }
+
+    /// Parse `dot`, make it the current graph and return its delta from the
+    /// previous one (everything is added on the first update).
+    #[wasm_bindgen]
+    pub fn update(&mut self, dot: &str) -> Result<JsGraphDelta, JsValue> {
+        let next = parse_graph(dot).map_err(|e| JsValue::from_str(&e))?;
+        let d = compute_delta(self.current.as_ref().unwrap_or(&GraphData::default()), &next);
+        self.current = Some(next);
+        to_js(&d)
+    }
+
+    /// The current graph, or `undefined` before the first update.
+    #[wasm_bindgen]
+    pub fn current(&self) -> Result<Option<JsGraphData>, JsValue> {
+        self.current.as_ref().map(to_js).transpose()
+    }
+
+    /// Forget the current graph.
+    #[wasm_bindgen]
+    pub fn reset(&mut self) {
+        self.current = None;
+    }
+}
+
+/// A `ForceLayout` driven from JS; positions follow the order of `parse(dot).nodes`.
+#[wasm_bindgen]
+pub struct DotLayout {
+    layout: ForceLayout,
+}
+
+#[wasm_bindgen]
+impl DotLayout {
+    #[wasm_bindgen(constructor)]
+    pub fn new(dot: &str, seed: u64) -> Result<DotLayout, JsValue> {
+        let g = parse_graph(dot).map_err(|e| JsValue::from_str(&e))?;
+        let options = LayoutOptions { seed, ..LayoutOptions::default() };
+        Ok(DotLayout { layout: ForceLayout::new(&g, options) })
+    }
+
+    /// Advance one tick; returns the largest node displacement.
+    #[wasm_bindgen]
+    pub fn step(&mut self) -> f32 {
+        self.layout.step()
+    }
+
+    /// Returns the number of steps taken.
+    #[wasm_bindgen]
+    pub fn run_until_converged(&mut self, max_steps: usize) -> usize {
+        self.layout.run_until_converged(max_steps)
+    }
+
+    #[wasm_bindgen]
+    pub fn pin(&mut self, id: &str, x: f32, y: f32, z: f32) -> Result<(), JsValue> {
+        self.layout.pin(id, [x, y, z]).map_err(|e| JsValue::from_str(&e))
+    }
+
+    #[wasm_bindgen]
+    pub fn unpin(&mut self, id: &str) -> Result<(), JsValue> {
+        self.layout.unpin(id).map_err(|e| JsValue::from_str(&e))
+    }
+
+    #[wasm_bindgen]
+    pub fn reheat(&mut self, alpha: f32) {
+        self.layout.reheat(alpha);
+    }
+
+    /// `x, y, z` per node, as a `Float32Array`.
+    #[wasm_bindgen]
+    pub fn positions(&self) -> Vec<f32> {
+        self.layout.flat_positions()
     }
 }
@@ -0,0 +1,418 @@
+//! Hygiene checks over DOT source. These work on the syntax tree rather than
+//! `GraphData`, since they need to know which nodes were declared explicitly
+//! and how often each attribute was set.
+
+use crate::color::invalid_colors;
+use crate::graph::{graph_from_ast, parse_ast, unquote, Attr, Stmt, Subgraph};
+use crate::weight::{parse_weight, WeightOptions};
+use dot_parser::ast::{self, either::Either};
+use serde::{Serialize, Deserialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Stable identifier of the check, e.g. `duplicate-edge`.
+    pub code: String,
+    pub message: String,
+    /// Node ids the diagnostic is about.
+    pub subject: Vec<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct LintOptions {
+    /// Nodes every other node must be reachable from, in addition to nodes
+    /// with `root=true` and the graph's `root` attribute.
+    pub roots: Vec<String>,
+    /// Edge attributes that must hold valid weights.
+    #[serde(default)]
+    pub weights: WeightOptions,
+}
+
+fn diagnostic(severity: Severity, code: &str, message: String, subject: Vec<String>) -> Diagnostic {
+    Diagnostic { severity, code: code.to_string(), message, subject }
+}
+
+/// Attribute assignments of an attribute list, in order.
+fn list_pairs(list: &ast::AttrList<Attr>) -> Vec<(&str, &str)> {
+    list.elems.iter()
+        .flat_map(|alist| alist.elems.iter().map(|(k, v)| (k.as_str(), v.text.as_str())))
+        .collect()
+}
+
+fn pairs(attr: &Option<ast::AttrList<Attr>>) -> Vec<(&str, &str)> {
+    attr.as_ref().map(list_pairs).unwrap_or_default()
+}
+
+/// Every statement, including those nested in subgraphs, in source order.
+fn flatten<'g>(stmts: &'g [Stmt], out: &mut Vec<&'g Stmt>) {
+    for stmt in stmts {
+        out.push(stmt);
+        match stmt {
+            ast::Stmt::Subgraph(sub) => flatten(&sub.stmts.stmts, out),
+            ast::Stmt::EdgeStmt(edge) => {
+                let mut end = &edge.from;
+                let mut rhs = Some(&edge.next);
+                loop {
+                    if let Either::Right(sub) = end {
+                        flatten(&sub.stmts.stmts, out);
+                    }
+                    match rhs {
+                        Some(next) => {
+                            end = &next.to;
+                            rhs = next.next.as_deref();
+                        }
+                        None => break,
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Node ids an edge endpoint stands for: one node, or every node a subgraph
+/// mentions. Ids are unquoted, so `a` and `"a"` are the same node.
+fn endpoint_ids(end: &Either<ast::NodeID, Subgraph>) -> Vec<String> {
+    match end {
+        Either::Left(node) => vec![unquote(&node.id)],
+        Either::Right(sub) => {
+            let mut stmts = Vec::new();
+            flatten(&sub.stmts.stmts, &mut stmts);
+            let mut ids: Vec<String> = Vec::new();
+            for stmt in stmts {
+                let found = match stmt {
+                    ast::Stmt::NodeStmt(node) => vec![unquote(&node.node.id)],
+                    ast::Stmt::EdgeStmt(edge) => edge_pairs(edge).into_iter().flat_map(|(a, b)| vec![a, b]).collect(),
+                    _ => Vec::new(),
+                };
+                for id in found {
+                    if !ids.contains(&id) {
+                        ids.push(id);
+                    }
+                }
+            }
+            ids
+        }
+    }
+}
+
+/// The `(source, target)` pairs of an edge statement, expanding subgraph endpoints.
+fn edge_pairs(edge: &ast::EdgeStmt<Attr>) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+    let mut from = endpoint_ids(&edge.from);
+    let mut rhs = Some(&edge.next);
+    while let Some(next) = rhs {
+        let to = endpoint_ids(&next.to);
+        for a in &from {
+            for b in &to {
+                result.push((a.clone(), b.clone()));
+            }
+        }
+        from = to;
+        rhs = next.next.as_deref();
+    }
+    result
+}
+
+fn is_true(value: &str) -> bool {
+    matches!(unquote(value).to_ascii_lowercase().as_str(), "true" | "yes" | "1")
+}
+
+/// Lint DOT source; diagnostics are sorted by decreasing severity.
+pub fn lint_dot(dot: &str, options: &LintOptions) -> Result<Vec<Diagnostic>, String> {
+    let graph = parse_ast(dot)?;
+    let mut stmts = Vec::new();
+    flatten(&graph.stmts.stmts, &mut stmts);
+
+    let mut diagnostics = Vec::new();
+    // Declared node ids in order, and as a set for lookups.
+    let mut declared: Vec<String> = Vec::new();
+    let mut is_declared: HashSet<String> = HashSet::new();
+    // node id -> attribute -> values it was given, in order
+    let mut node_attrs: HashMap<String, BTreeMap<&str, Vec<&str>>> = HashMap::new();
+    let mut edges: Vec<(String, String)> = Vec::new();
+    let mut roots: BTreeSet<String> = options.roots.iter().cloned().collect();
+    // (description, node ids, value) of every label
+    let mut labels: Vec<(String, Vec<String>, &str)> = Vec::new();
+    let is_weight = |k: &str| options.weights.attributes.iter().any(|a| a == k);
+
+    for stmt in stmts {
+        match stmt {
+            ast::Stmt::NodeStmt(node) => {
+                let id = unquote(&node.node.id);
+                if is_declared.insert(id.clone()) {
+                    declared.push(id.clone());
+                }
+                let attrs = node_attrs.entry(id.clone()).or_default();
+                for (k, v) in pairs(&node.attr) {
+                    attrs.entry(k).or_default().push(v);
+                    if k == "root" && is_true(v) {
+                        roots.insert(id.to_string());
+                    }
+                    if k.ends_with("label") {
+                        labels.push((format!("node {}", id), vec![id.to_string()], v));
+                    }
+                }
+            }
+            ast::Stmt::EdgeStmt(edge) => {
+                let expanded = edge_pairs(edge);
+                if let Some((a, b)) = expanded.first() {
+                    for (k, v) in pairs(&edge.attr) {
+                        if k.ends_with("label") {
+                            labels.push((format!("edge {} -> {}", a, b), vec![a.to_string(), b.to_string()], v));
+                        }
+                        if is_weight(k) && parse_weight(v).is_none() {
+                            diagnostics.push(diagnostic(Severity::Warning, "invalid-weight",
+                                format!("Edge {} -> {} sets {} to {:?}, which is not a non-negative number", a, b, k, v),
+                                vec![a.to_string(), b.to_string()]));
+                        }
+                    }
+                }
+                edges.extend(expanded);
+            }
+            ast::Stmt::AttrStmt(ast::AttrStmt::Edge(list)) => {
+                for (k, v) in list_pairs(list) {
+                    if is_weight(k) && parse_weight(v).is_none() {
+                        diagnostics.push(diagnostic(Severity::Warning, "invalid-weight",
+                            format!("Default edge {} is {:?}, which is not a non-negative number", k, v), Vec::new()));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The graph's own `root`; subgraphs can't set it.
+    for stmt in &graph.stmts.stmts {
+        match stmt {
+            ast::Stmt::AttrStmt(ast::AttrStmt::Graph(list)) => {
+                for (k, v) in list_pairs(list) {
+                    if k == "root" {
+                        roots.insert(unquote(v));
+                    }
+                }
+            }
+            ast::Stmt::IDEq(k, v) if k == "root" => {
+                roots.insert(unquote(v));
+            }
+            _ => {}
+        }
+    }
+
+    // Edges to nodes that were never declared with a node statement.
+    let mut undeclared: BTreeSet<&str> = BTreeSet::new();
+    for (a, b) in &edges {
+        for id in [a, b] {
+            if !is_declared.contains(id) {
+                undeclared.insert(id);
+            }
+        }
+    }
+    for id in &undeclared {
+        diagnostics.push(diagnostic(Severity::Error, "undeclared-node",
+            format!("Edge refers to undeclared node {}", id), vec![id.to_string()]));
+    }
+
+    // Duplicate edges and self-loops.
+    let mut seen: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+    for (a, b) in &edges {
+        let (a, b) = (a.as_str(), b.as_str());
+        let key = if graph.is_digraph || a <= b { (a, b) } else { (b, a) };
+        *seen.entry(key).or_insert(0) += 1;
+        if a == b {
+            diagnostics.push(diagnostic(Severity::Warning, "self-loop",
+                format!("Node {} has an edge to itself", a), vec![a.to_string()]));
+        }
+    }
+    for ((a, b), count) in seen.iter().filter(|(_, c)| **c > 1) {
+        diagnostics.push(diagnostic(Severity::Warning, "duplicate-edge",
+            format!("Edge {} -> {} is declared {} times", a, b, count),
+            vec![a.to_string(), b.to_string()]));
+    }
+
+    // Where each edge leads (both ways in undirected graphs). Every endpoint
+    // has an entry, so nodes without one have no edges.
+    let mut adjacent: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (a, b) in &edges {
+        adjacent.entry(a).or_default().push(b);
+        let back = adjacent.entry(b).or_default();
+        if !graph.is_digraph {
+            back.push(a);
+        }
+    }
+
+    // Declared nodes without any edge.
+    for id in &declared {
+        if !adjacent.contains_key(id.as_str()) {
+            diagnostics.push(diagnostic(Severity::Info, "isolated-node",
+                format!("Node {} has no edges", id), vec![id.to_string()]));
+        }
+    }
+
+    // Nodes that no root reaches.
+    if !roots.is_empty() {
+        let mut all: Vec<&str> = declared.iter().map(String::as_str).collect();
+        all.extend(undeclared.iter().copied());
+        let mut reached: HashSet<&str> = HashSet::new();
+        let mut queue: VecDeque<&str> = VecDeque::new();
+        for root in &roots {
+            match all.iter().find(|id| **id == unquote(root)) {
+                Some(id) => {
+                    reached.insert(id);
+                    queue.push_back(id);
+                }
+                None => diagnostics.push(diagnostic(Severity::Error, "unknown-root",
+                    format!("Root {} is not a node of the graph", root), vec![root.clone()])),
+            }
+        }
+        while let Some(n) = queue.pop_front() {
+            for &next in adjacent.get(n).into_iter().flatten() {
+                if reached.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        for id in all.iter().filter(|id| !reached.contains(*id)) {
+            diagnostics.push(diagnostic(Severity::Error, "unreachable-node",
+                format!("Node {} is not reachable from any root", id), vec![id.to_string()]));
+        }
+    }
+
+    // Attributes given different values across redeclarations.
+    for id in &declared {
+        for (key, values) in &node_attrs[id.as_str()] {
+            let distinct: BTreeSet<String> = values.iter().map(|v| unquote(v)).collect();
+            if distinct.len() > 1 {
+                let list: Vec<String> = distinct.into_iter().collect();
+                diagnostics.push(diagnostic(Severity::Error, "conflicting-attribute",
+                    format!("Node {} sets {} to conflicting values: {}", id, key, list.join(", ")),
+                    vec![id.to_string()]));
+            }
+        }
+    }
+
+    // Colors that don't resolve, e.g. in a Brewer color scheme, with the
+    // defaults and `colorscheme` in effect.
+    let data = graph_from_ast(&graph);
+    for node in &data.nodes {
+        for (attribute, error) in invalid_colors(&node.attributes) {
+            diagnostics.push(diagnostic(Severity::Warning, "invalid-color",
+                format!("Node {} sets {} to {:?}: {}", node.id, attribute, node.attributes[attribute], error),
+                vec![node.id.clone()]));
+        }
+    }
+    for link in &data.links {
+        for (attribute, error) in invalid_colors(&link.attributes) {
+            diagnostics.push(diagnostic(Severity::Warning, "invalid-color",
+                format!("Edge {} -> {} sets {} to {:?}: {}", link.source, link.target, attribute, link.attributes[attribute], error),
+                vec![link.source.clone(), link.target.clone()]));
+        }
+    }
+    for cluster in &data.clusters {
+        for (attribute, error) in invalid_colors(&cluster.attributes) {
+            diagnostics.push(diagnostic(Severity::Warning, "invalid-color",
+                format!("Cluster {} sets {} to {:?}: {}", cluster.id, attribute, cluster.attributes[attribute], error),
+                Vec::new()));
+        }
+    }
+
+    // Labels with a literal line break instead of `\n`.
+    for (owner, subject, value) in labels {
+        if value.contains('\n') {
+            diagnostics.push(diagnostic(Severity::Warning, "unescaped-newline",
+                format!("Label of {} contains an unescaped newline; use \\n", owner), subject));
+        }
+    }
+
+    diagnostics.sort_by_key(|d| Reverse(d.severity));
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(dot: &str) -> Vec<String> {
+        lint_dot(dot, &LintOptions::default()).unwrap().into_iter().map(|d| d.code).collect()
+    }
+
+    #[test]
+    fn each_check_reports_its_code() {
+        let dot = "digraph {
+            a [root=true]; b; c; lonely;
+            a [shape=box]; a [shape=circle];
+            a -> b; a -> b; b -> b; c -> d;
+            b -> c [weight=-1];
+            c [label=\"x
+y\"];
+        }";
+        let options = LintOptions { roots: vec!["ghost".to_string()], ..LintOptions::default() };
+        let diagnostics = lint_dot(dot, &options).unwrap();
+        let found: BTreeSet<(&str, Vec<&str>)> = diagnostics.iter()
+            .map(|d| (d.code.as_str(), d.subject.iter().map(String::as_str).collect()))
+            .collect();
+        let expected: BTreeSet<(&str, Vec<&str>)> = vec![
+            ("undeclared-node", vec!["d"]),
+            ("unknown-root", vec!["ghost"]),
+            ("unreachable-node", vec!["lonely"]),
+            ("conflicting-attribute", vec!["a"]),
+            ("duplicate-edge", vec!["a", "b"]),
+            ("self-loop", vec!["b"]),
+            ("invalid-weight", vec!["b", "c"]),
+            ("unescaped-newline", vec!["c"]),
+            ("isolated-node", vec!["lonely"]),
+        ].into_iter().collect();
+        assert_eq!(found, expected);
+        assert_eq!(diagnostics.len(), expected.len());
+        assert!(diagnostics.windows(2).all(|w| w[0].severity >= w[1].severity));
+    }
+
+    #[test]
+    fn clean_graphs_have_no_diagnostics() {
+        let dot = "digraph { root=a; a; b [label=\"x\\ny\"]; a -> b [weight=2]; subgraph { b -> c } c }";
+        assert!(codes(dot).is_empty());
+    }
+
+    #[test]
+    fn large_graphs_are_linted() {
+        let n = 20_000;
+        let mut dot = String::from("digraph { root=n0;\n");
+        for i in 0..n {
+            dot.push_str(&format!("n{i}; n{i} -> n{j};\n", i = i, j = i + 1));
+        }
+        dot.push('}');
+        let diagnostics = lint_dot(&dot, &LintOptions::default()).unwrap();
+        let found: Vec<(&str, &str)> = diagnostics.iter().map(|d| (d.code.as_str(), d.subject[0].as_str())).collect();
+        assert_eq!(found, vec![("undeclared-node", "n20000")]);
+    }
+
+    #[test]
+    fn quoted_and_bare_ids_are_one_node() {
+        assert!(codes(r#"digraph { "a" [shape=box]; b; a -> "b" }"#).is_empty());
+        assert_eq!(codes(r#"digraph { a -> b; "a" -> "b" }"#), vec!["undeclared-node", "undeclared-node", "duplicate-edge"]);
+    }
+
+    #[test]
+    fn unresolved_colors_are_reported() {
+        let dot = "digraph { node [colorscheme=blues9]; a [color=3]; b [fillcolor=\"/accent8/1\", color=red]; a -> b [color=\"/svg/red\"] }";
+        let diagnostics = lint_dot(dot, &LintOptions::default()).unwrap();
+        let colors: Vec<(&str, &str)> = diagnostics.iter()
+            .filter(|d| d.code == "invalid-color")
+            .map(|d| (d.subject[0].as_str(), d.message.as_str()))
+            .collect();
+        assert_eq!(colors, vec![
+            ("a", "Node a sets color to \"3\": Unsupported color scheme blues9"),
+            ("b", "Node b sets fillcolor to \"/accent8/1\": Unsupported color scheme accent8"),
+        ]);
+    }
+}
@@ -1,70 +1,684 @@
 use std::env;
+use std::fmt;
 use std::fs;
-use dot_parser_delta_computator::{parse_graph, compute_delta};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use serde::Serialize;
+use serde_json::Value;
+use dot_parser_delta_computator::{
+    anonymize, assign_weights, canonicalize, check_policy, compose, compute_centrality, compute_delta, compute_delta_mapped, diff_graph,
+    find_isomorphism, find_paths, fingerprint, graph_stats, json_patch, lint_dot, parse_graph,
+    parse_mapping, parse_policy, query_graph, render_svg, write_dot, Diagnostic, GraphData, GraphDelta, GraphStats,
+    AnonymizeOptions, ComposeMapping, ComposePart, IsomorphismOptions, LintOptions, NodeMapping, PatchOp, PathReport, Severity, Violation,
+    WeightOptions, DEFAULT_PATH_LIMIT,
+};
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+/// Lint errors, policy violations, or a command that could not complete
+/// (e.g. an unknown node).
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_IO: i32 = 3;
+/// Invalid DOT, query or policy.
+const EXIT_PARSE: i32 = 4;
 
-    if args.len() < 2 {
-        eprintln!("Usage:");
-        eprintln!("  {} parse <file.dot>", args[0]);
-        eprintln!("  {} delta <file1.dot> <file2.dot>", args[0]);
-        std::process::exit(1);
+enum CliError {
+    Usage(String),
+    Io(String),
+    Parse(String),
+    Failed(String),
+}
+
+impl CliError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => EXIT_USAGE,
+            CliError::Io(_) => EXIT_IO,
+            CliError::Parse(_) => EXIT_PARSE,
+            CliError::Failed(_) => EXIT_FAILURE,
+        }
     }
+}
 
-    match args[1].as_str() {
-        "parse" => {
-            if args.len() != 3 {
-                eprintln!("Usage: {} parse <file.dot>", args[0]);
-                std::process::exit(1);
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Usage(msg) | CliError::Io(msg) | CliError::Parse(msg) | CliError::Failed(msg) => {
+                write!(f, "{}", msg)
             }
+        }
+    }
+}
 
-            let filename = &args[2];
-            let content = fs::read_to_string(filename)
-                .unwrap_or_else(|_| panic!("Failed to read file: {}", filename));
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Jsonl,
+    Text,
+    Dot,
+    Svg,
+    JsonPatch,
+}
 
-            match parse_graph(&content) {
-                Ok(graph) => {
-                    let json = serde_json::to_string_pretty(&graph)
-                        .expect("Failed to serialize GraphData to JSON");
-                    println!("{}", json);
-                }
-                Err(e) => {
-                    eprintln!("Error parsing DOT file: {}", e);
-                    std::process::exit(1);
-                }
+impl Format {
+    fn parse(name: &str) -> Result<Format, CliError> {
+        match name {
+            "json" => Ok(Format::Json),
+            "jsonl" => Ok(Format::Jsonl),
+            "text" => Ok(Format::Text),
+            "dot" => Ok(Format::Dot),
+            "svg" => Ok(Format::Svg),
+            "json-patch" => Ok(Format::JsonPatch),
+            _ => Err(CliError::Usage(format!(
+                "Unknown format: {} (expected json, jsonl, text, dot, svg or json-patch)", name))),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Jsonl => "jsonl",
+            Format::Text => "text",
+            Format::Dot => "dot",
+            Format::Svg => "svg",
+            Format::JsonPatch => "json-patch",
+        }
+    }
+}
+
+struct Command {
+    name: &'static str,
+    args: &'static str,
+    /// Command-specific flags, besides `--format` and `--output`.
+    flags: &'static [&'static str],
+    formats: &'static [Format],
+    default_format: Format,
+}
+
+const GRAPH_FORMATS: &[Format] = &[Format::Json, Format::Jsonl, Format::Text, Format::Dot];
+const REPORT_FORMATS: &[Format] = &[Format::Json, Format::Jsonl, Format::Text];
+const DELTA_FORMATS: &[Format] = &[Format::Json, Format::Jsonl, Format::Text, Format::JsonPatch];
+const FINGERPRINT_FORMATS: &[Format] = &[Format::Text, Format::Json, Format::Dot];
+const RENDER_FORMATS: &[Format] = &[Format::Dot, Format::Svg, Format::Json];
+
+const COMMANDS: &[Command] = &[
+    Command { name: "parse", args: "<file.dot>", flags: &["--weight-attr"], formats: GRAPH_FORMATS, default_format: Format::Json },
+    Command { name: "delta", args: "<old.dot> <new.dot>", flags: &["--structural", "--labels", "--attributes"], formats: DELTA_FORMATS, default_format: Format::Json },
+    Command { name: "compose", args: "<file.dot>...", flags: &["--prefixed", "--links"], formats: GRAPH_FORMATS, default_format: Format::Json },
+    Command { name: "anonymize", args: "<file.dot>", flags: &["--key-file", "--keep", "--reverse"], formats: GRAPH_FORMATS, default_format: Format::Json },
+    Command { name: "diff-render", args: "<old.dot> <new.dot>", flags: &[], formats: RENDER_FORMATS, default_format: Format::Dot },
+    Command { name: "fingerprint", args: "<file.dot>", flags: &[], formats: FINGERPRINT_FORMATS, default_format: Format::Text },
+    Command { name: "isomorphic", args: "<a.dot> <b.dot>", flags: &["--labels", "--attributes"], formats: REPORT_FORMATS, default_format: Format::Json },
+    Command { name: "query", args: "<file.dot> <query>", flags: &[], formats: GRAPH_FORMATS, default_format: Format::Json },
+    Command { name: "stats", args: "<file.dot>", flags: &["--json"], formats: REPORT_FORMATS, default_format: Format::Text },
+    Command { name: "path", args: "<file.dot> <from> <to>", flags: &["--limit", "--weight-attr"], formats: REPORT_FORMATS, default_format: Format::Json },
+    Command { name: "centrality", args: "<file.dot>", flags: &[], formats: GRAPH_FORMATS, default_format: Format::Json },
+    Command { name: "lint", args: "<file.dot>", flags: &["--root", "--strict", "--weight-attr"], formats: REPORT_FORMATS, default_format: Format::Json },
+    Command { name: "check", args: "<file.dot> <policy.json>", flags: &[], formats: REPORT_FORMATS, default_format: Format::Json },
+];
+
+fn flag_usage(flag: &str) -> &'static str {
+    match flag {
+        "--json" => "[--json]",
+        "--limit" => "[--limit N]",
+        "--root" => "[--root <node>]...",
+        "--strict" => "[--strict]",
+        "--structural" => "[--structural]",
+        "--labels" => "[--labels]",
+        "--attributes" => "[--attributes]",
+        "--weight-attr" => "[--weight-attr <name>]...",
+        "--prefixed" => "[--prefixed]",
+        "--links" => "[--links <mapping.json>]",
+        "--key-file" => "[--key-file <file>]",
+        "--keep" => "[--keep <attribute>]...",
+        "--reverse" => "[--reverse <mapping.json>]",
+        _ => "",
+    }
+}
+
+fn command_usage(program: &str, command: &Command) -> String {
+    let mut line = format!("{} {} {}", program, command.name, command.args);
+    for flag in command.flags {
+        line.push(' ');
+        line.push_str(flag_usage(flag));
+    }
+    line.push_str(" [--format ");
+    line.push_str(&command.formats.iter().map(|f| f.name()).collect::<Vec<_>>().join("|"));
+    line.push_str("] [--output <file>]");
+    line
+}
+
+fn usage(program: &str) -> String {
+    let mut text = String::from("Usage:\n");
+    for command in COMMANDS {
+        text.push_str(&format!("  {}\n", command_usage(program, command)));
+    }
+    text.push_str("\nUse - as a file name to read standard input.\n");
+    text.push_str("anonymize reads its key from --key-file or the DOT_DELTA_KEY environment variable.\n");
+    text.push_str("Exit codes: 0 ok, 1 check failed, 2 usage error, 3 I/O error, 4 parse error.\n");
+    text
+}
+
+/// Arguments of one command invocation.
+struct Invocation {
+    positional: Vec<String>,
+    format: Format,
+    output: Option<String>,
+    limit: Option<usize>,
+    roots: Vec<String>,
+    strict: bool,
+    structural: bool,
+    isomorphism: IsomorphismOptions,
+    /// Attributes to read link weights from, if given with `--weight-attr`.
+    weights: Option<WeightOptions>,
+    prefixed: bool,
+    links: Option<String>,
+    key_file: Option<String>,
+    keep: Option<Vec<String>>,
+    reverse: Option<String>,
+}
+
+fn parse_args(program: &str, command: &Command, args: &[String]) -> Result<Invocation, CliError> {
+    let usage_error = || CliError::Usage(format!("Usage: {}", command_usage(program, command)));
+    let mut inv = Invocation {
+        positional: Vec::new(),
+        format: command.default_format,
+        output: None,
+        limit: None,
+        roots: Vec::new(),
+        strict: false,
+        structural: false,
+        isomorphism: IsomorphismOptions::default(),
+        weights: None,
+        prefixed: false,
+        links: None,
+        key_file: None,
+        keep: None,
+        reverse: None,
+    };
+    let mut format = None;
+
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let flag = match arg.as_str() {
+            "-f" => "--format",
+            "-o" => "--output",
+            "--" => {
+                inv.positional.extend(rest.by_ref().cloned());
+                break;
+            }
+            "-" => {
+                inv.positional.push(arg.clone());
+                continue;
             }
+            a if a.starts_with('-') => a,
+            _ => {
+                inv.positional.push(arg.clone());
+                continue;
+            }
+        };
+        if !["--format", "--output"].contains(&flag) && !command.flags.contains(&flag) {
+            return Err(CliError::Usage(format!("Unknown option for {}: {}", command.name, arg)));
+        }
+        let mut value = || rest.next().cloned()
+            .ok_or_else(|| CliError::Usage(format!("Missing value after {}", arg)));
+        match flag {
+            "--format" => format = Some(Format::parse(&value()?)?),
+            "--output" => inv.output = Some(value()?),
+            "--json" => format = Some(Format::Json),
+            "--limit" => {
+                let limit = value()?;
+                inv.limit = Some(limit.parse()
+                    .map_err(|_| CliError::Usage(format!("Invalid limit: {}", limit)))?);
+            }
+            "--root" => inv.roots.push(value()?),
+            "--strict" => inv.strict = true,
+            "--structural" => inv.structural = true,
+            "--labels" => inv.isomorphism.match_labels = true,
+            "--attributes" => inv.isomorphism.match_attributes = true,
+            "--key-file" => inv.key_file = Some(value()?),
+            "--keep" => {
+                let attribute = value()?;
+                inv.keep.get_or_insert_with(Vec::new).push(attribute);
+            }
+            "--reverse" => inv.reverse = Some(value()?),
+            "--prefixed" => inv.prefixed = true,
+            "--links" => inv.links = Some(value()?),
+            "--weight-attr" => {
+                let attribute = value()?;
+                inv.weights.get_or_insert_with(|| WeightOptions { attributes: Vec::new() }).attributes.push(attribute);
+            }
+            _ => return Err(usage_error()),
+        }
+    }
+
+    // A trailing `<arg>...` takes one or more values.
+    let expected = command.args.split_whitespace().count();
+    let variadic = command.args.ends_with("...");
+    if inv.positional.len() < expected || (!variadic && inv.positional.len() != expected) {
+        return Err(usage_error());
+    }
+    if let Some(format) = format {
+        if !command.formats.contains(&format) {
+            return Err(CliError::Usage(format!("{} does not support --format {}", command.name, format.name())));
+        }
+        inv.format = format;
+    }
+    // A structural delta pairs nodes with different ids, which a patch
+    // against the old document's ids can't express.
+    if inv.structural && inv.format == Format::JsonPatch {
+        return Err(CliError::Usage("--structural cannot be combined with --format json-patch".to_string()));
+    }
+    Ok(inv)
+}
+
+/// Read a file, or standard input for `-`.
+fn read_input(path: &str) -> Result<String, CliError> {
+    if path == "-" {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content)
+            .map_err(|e| CliError::Io(format!("Failed to read standard input: {}", e)))?;
+        Ok(content)
+    } else {
+        fs::read_to_string(path).map_err(|e| CliError::Io(format!("Failed to read {}: {}", path, e)))
+    }
+}
+
+fn input_name(path: &str) -> &str {
+    if path == "-" { "standard input" } else { path }
+}
+
+fn read_graph(path: &str) -> Result<GraphData, CliError> {
+    let content = read_input(path)?;
+    parse_graph(&content).map_err(|e| CliError::Parse(format!("Error parsing {}: {}", input_name(path), e)))
+}
+
+fn write_output(output: Option<&str>, text: &str) -> Result<(), CliError> {
+    match output {
+        Some(path) => fs::write(path, text).map_err(|e| CliError::Io(format!("Failed to write {}: {}", path, e))),
+        None => io::stdout().write_all(text.as_bytes())
+            .map_err(|e| CliError::Io(format!("Failed to write standard output: {}", e))),
+    }
+}
+
+/// Result of a command, rendered according to `--format`.
+enum Output {
+    Graph(GraphData),
+    Delta(GraphDelta),
+    Patch(Vec<PatchOp>),
+    Fingerprint(String),
+    Isomorphism(Option<NodeMapping>),
+    Stats(GraphStats),
+    Paths(PathReport),
+    Diagnostics(Vec<Diagnostic>),
+    Violations(Vec<Violation>),
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Value, CliError> {
+    serde_json::to_value(value).map_err(|e| CliError::Failed(format!("Failed to serialize output: {}", e)))
+}
+
+/// `value` with an extra `key` field, for JSON lines records.
+fn tagged<T: Serialize>(key: &str, tag: &str, value: &T) -> Result<Value, CliError> {
+    let mut json = to_json(value)?;
+    if let Value::Object(map) = &mut json {
+        map.insert(key.to_string(), Value::from(tag));
+    }
+    Ok(json)
+}
+
+fn attribute_list(attributes: &[(String, String)]) -> String {
+    if attributes.is_empty() {
+        String::new()
+    } else {
+        let list: Vec<String> = attributes.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        format!(" [{}]", list.join(", "))
+    }
+}
+
+fn graph_text(graph: &GraphData) -> String {
+    let mut text = String::new();
+    for node in &graph.nodes {
+        let mut attributes: Vec<(String, String)> = node.attributes.iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        if let Some(cluster) = node.cluster {
+            attributes.push(("cluster".to_string(), cluster.to_string()));
+        }
+        attributes.extend(node.metrics.iter().map(|(k, v)| (k.clone(), format!("{:.4}", v))));
+        text.push_str(&format!("{}{}\n", node.id, attribute_list(&attributes)));
+    }
+    for link in &graph.links {
+        let attributes: Vec<(String, String)> = link.attributes.iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        text.push_str(&format!("{} -> {}{}\n", link.source, link.target, attribute_list(&attributes)));
+    }
+    text
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Info => "info",
+        Severity::Warning => "warning",
+        Severity::Error => "error",
+    }
+}
+
+fn paths_text(report: &PathReport) -> String {
+    let mut text = format!("From:      {}\nTo:        {}\n", report.from, report.to);
+    text.push_str(&format!("Reachable: {}\n", if report.reachable { "yes" } else { "no" }));
+    if let Some(path) = &report.shortest {
+        text.push_str(&format!("Shortest:  {}\n", path.join(" -> ")));
+    }
+    if let Some(weighted) = &report.weighted {
+        text.push_str(&format!("Cheapest:  {} (cost {})\n", weighted.path.join(" -> "), weighted.cost));
+    }
+    text.push_str(&format!("Paths:     {}\n", report.all_paths.len()));
+    for path in &report.all_paths {
+        text.push_str(&format!("  {}\n", path.join(" -> ")));
+    }
+    text
+}
+
+fn render(output: &Output, format: Format) -> Result<String, CliError> {
+    let lines = |records: Vec<Value>| -> String {
+        records.iter().map(|r| format!("{}\n", r)).collect()
+    };
+
+    let text = match (output, format) {
+        (Output::Graph(graph), Format::Json) => format!("{:#}\n", to_json(graph)?),
+        (Output::Delta(delta), Format::Json) => format!("{:#}\n", to_json(delta)?),
+        (Output::Fingerprint(hash), Format::Json) => format!("{:#}\n", serde_json::json!({ "fingerprint": hash })),
+        (Output::Isomorphism(mapping), Format::Json) => {
+            format!("{:#}\n", serde_json::json!({ "isomorphic": mapping.is_some(), "mapping": mapping }))
+        }
+        (Output::Patch(ops), Format::JsonPatch) => format!("{:#}\n", to_json(ops)?),
+        (Output::Stats(stats), Format::Json) => format!("{:#}\n", to_json(stats)?),
+        (Output::Paths(report), Format::Json) => format!("{:#}\n", to_json(report)?),
+        (Output::Diagnostics(diagnostics), Format::Json) => format!("{:#}\n", to_json(diagnostics)?),
+        (Output::Violations(violations), Format::Json) => format!("{:#}\n", to_json(violations)?),
+
+        (Output::Graph(graph), Format::Jsonl) => {
+            let mut records = Vec::new();
+            for node in &graph.nodes {
+                records.push(tagged("type", "node", node)?);
+            }
+            for link in &graph.links {
+                records.push(tagged("type", "link", link)?);
+            }
+            lines(records)
+        }
+        (Output::Delta(delta), Format::Jsonl) => {
+            let mut records = Vec::new();
+            for id in &delta.added_nodes {
+                records.push(serde_json::json!({ "change": "added_node", "id": id }));
+            }
+            for id in &delta.removed_nodes {
+                records.push(serde_json::json!({ "change": "removed_node", "id": id }));
+            }
+            for (source, target) in &delta.added_edges {
+                records.push(serde_json::json!({ "change": "added_edge", "source": source, "target": target }));
+            }
+            for (source, target) in &delta.removed_edges {
+                records.push(serde_json::json!({ "change": "removed_edge", "source": source, "target": target }));
+            }
+            for id in &delta.added_clusters {
+                records.push(serde_json::json!({ "change": "added_cluster", "id": id }));
+            }
+            for id in &delta.removed_clusters {
+                records.push(serde_json::json!({ "change": "removed_cluster", "id": id }));
+            }
+            for moved in &delta.moved_nodes {
+                records.push(serde_json::json!({ "change": "moved_node", "id": moved.node, "from": moved.from, "to": moved.to }));
+            }
+            lines(records)
+        }
+        (Output::Isomorphism(mapping), Format::Jsonl) => lines(mapping.iter().flatten()
+            .map(|(from, to)| serde_json::json!({ "from": from, "to": to }))
+            .collect()),
+        (Output::Stats(stats), Format::Jsonl) => lines(vec![to_json(stats)?]),
+        (Output::Paths(report), Format::Jsonl) => lines(vec![to_json(report)?]),
+        (Output::Diagnostics(diagnostics), Format::Jsonl) => {
+            lines(diagnostics.iter().map(to_json).collect::<Result<_, _>>()?)
+        }
+        (Output::Violations(violations), Format::Jsonl) => {
+            lines(violations.iter().map(to_json).collect::<Result<_, _>>()?)
+        }
+
+        (Output::Graph(graph), Format::Text) => graph_text(graph),
+        (Output::Delta(delta), Format::Text) => {
+            let mut text = String::new();
+            delta.added_nodes.iter().for_each(|id| text.push_str(&format!("+ {}\n", id)));
+            delta.removed_nodes.iter().for_each(|id| text.push_str(&format!("- {}\n", id)));
+            delta.added_edges.iter().for_each(|(a, b)| text.push_str(&format!("+ {} -> {}\n", a, b)));
+            delta.removed_edges.iter().for_each(|(a, b)| text.push_str(&format!("- {} -> {}\n", a, b)));
+            delta.added_clusters.iter().for_each(|id| text.push_str(&format!("+ cluster {}\n", id)));
+            delta.removed_clusters.iter().for_each(|id| text.push_str(&format!("- cluster {}\n", id)));
+            for moved in &delta.moved_nodes {
+                let place = |cluster: &Option<String>| cluster.as_deref().unwrap_or("(top level)").to_string();
+                text.push_str(&format!("~ {}: {} => {}\n", moved.node, place(&moved.from), place(&moved.to)));
+            }
+            text
+        }
+        (Output::Stats(stats), Format::Text) => stats.to_string(),
+        (Output::Fingerprint(hash), Format::Text) => format!("{}\n", hash),
+        (Output::Isomorphism(None), Format::Text) => "not isomorphic\n".to_string(),
+        (Output::Isomorphism(Some(mapping)), Format::Text) => mapping.iter()
+            .map(|(from, to)| format!("{} -> {}\n", from, to))
+            .collect(),
+        (Output::Paths(report), Format::Text) => paths_text(report),
+        (Output::Diagnostics(diagnostics), Format::Text) => diagnostics.iter()
+            .map(|d| format!("{}[{}]: {}\n", severity_name(d.severity), d.code, d.message))
+            .collect(),
+        (Output::Violations(violations), Format::Text) => violations.iter()
+            .map(|v| format!("{}: {} ({})\n", v.rule, v.message, v.path.join(" -> ")))
+            .collect(),
+
+        (Output::Graph(graph), Format::Dot) => write_dot(graph),
+        (Output::Graph(graph), Format::Svg) => render_svg(graph),
+        (_, format) => return Err(CliError::Usage(format!("Output cannot be written as {}", format.name()))),
+    };
+    Ok(text)
+}
+
+/// Run a command; returns the exit code after its output has been written.
+fn run(program: &str, args: &[String]) -> Result<i32, CliError> {
+    let name = match args.first() {
+        Some(name) => name.as_str(),
+        None => return Err(CliError::Usage(usage(program))),
+    };
+    if name == "-h" || name == "--help" || name == "help" {
+        write_output(None, &usage(program))?;
+        return Ok(0);
+    }
+    let command = COMMANDS.iter().find(|c| c.name == name)
+        .ok_or_else(|| CliError::Usage(format!("Unknown command: {}\n{}", name, usage(program))))?;
+    let inv = parse_args(program, command, &args[1..])?;
+    // Every input read through `read_input`; `path` and `query` take node
+    // ids and a query after their file, which may well be `-`.
+    let mut inputs: Vec<&String> = match command.name {
+        "path" | "query" => inv.positional.iter().take(1).collect(),
+        _ => inv.positional.iter().collect(),
+    };
+    inputs.extend(inv.links.iter().chain(&inv.key_file));
+    if inputs.iter().filter(|f| f.as_str() == "-").count() > 1 {
+        return Err(CliError::Usage("Standard input can only be read once".to_string()));
+    }
+
+    let mut exit_code = 0;
+    let output = match command.name {
+        "parse" => {
+            let mut graph = read_graph(&inv.positional[0])?;
+            if let Some(weights) = &inv.weights {
+                assign_weights(&mut graph, weights);
+            }
+            Output::Graph(graph)
         }
 
         "delta" => {
-            if args.len() != 4 {
-                eprintln!("Usage: {} delta <file1.dot> <file2.dot>", args[0]);
-                std::process::exit(1);
+            let g1 = read_graph(&inv.positional[0])?;
+            let g2 = read_graph(&inv.positional[1])?;
+            let mapping = if inv.structural {
+                let mapping = find_isomorphism(&g1, &g2, &inv.isomorphism).map_err(CliError::Failed)?;
+                if mapping.is_none() {
+                    eprintln!("Graphs are not isomorphic; comparing node ids");
+                }
+                mapping
+            } else {
+                None
+            };
+            let mut delta = match &mapping {
+                Some(mapping) => compute_delta_mapped(&g1, &g2, mapping),
+                None => compute_delta(&g1, &g2),
+            };
+            delta.added_nodes.sort();
+            delta.removed_nodes.sort();
+            delta.added_edges.sort();
+            delta.removed_edges.sort();
+            delta.moved_nodes.sort();
+            delta.added_clusters.sort();
+            delta.removed_clusters.sort();
+            if inv.format == Format::JsonPatch {
+                Output::Patch(json_patch(&g1, &g2, &delta))
+            } else {
+                Output::Delta(delta)
             }
+        }
 
-            let file1 = &args[2];
-            let file2 = &args[3];
+        "compose" => {
+            let mapping = match &inv.links {
+                Some(path) => parse_mapping(&read_input(path)?)
+                    .map_err(|e| CliError::Parse(format!("Error in {}: {}", input_name(path), e)))?,
+                None => ComposeMapping::default(),
+            };
+            let mut parts = Vec::new();
+            for path in &inv.positional {
+                let stem = || Path::new(input_name(path)).file_stem().map(|s| s.to_string_lossy().into_owned());
+                let prefix = match mapping.prefixes.get(path) {
+                    Some(prefix) => Some(prefix.clone()),
+                    None if inv.prefixed => stem(),
+                    None => None,
+                };
+                parts.push(ComposePart { origin: input_name(path).to_string(), prefix, graph: read_graph(path)? });
+            }
+            let graph = compose(&parts, &mapping.links)
+                .map_err(|e| CliError::Failed(format!("Error composing graphs: {}", e)))?;
+            Output::Graph(graph)
+        }
+
+        "anonymize" => {
+            let key = match &inv.key_file {
+                Some(path) => read_input(path)?.trim_end().to_string(),
+                None => env::var("DOT_DELTA_KEY").map_err(|_| CliError::Usage(
+                    "anonymize needs a key: pass --key-file <file> or set DOT_DELTA_KEY".to_string()))?,
+            };
+            if key.is_empty() {
+                return Err(CliError::Usage("The anonymization key is empty".to_string()));
+            }
+            let mut options = AnonymizeOptions::new(&key);
+            if let Some(keep) = &inv.keep {
+                options.keep_attributes = keep.clone();
+            }
+            let anonymized = anonymize(&read_graph(&inv.positional[0])?, &options)
+                .map_err(|e| CliError::Failed(format!("Error anonymizing: {}", e)))?;
+            if let Some(path) = &inv.reverse {
+                write_output(Some(path), &format!("{:#}\n", to_json(&anonymized.reverse)?))?;
+            }
+            Output::Graph(anonymized.graph)
+        }
 
-            let dot1 = fs::read_to_string(file1)
-                .unwrap_or_else(|_| panic!("Failed to read file: {}", file1));
-            let dot2 = fs::read_to_string(file2)
-                .unwrap_or_else(|_| panic!("Failed to read file: {}", file2));
+        "diff-render" => {
+            let g1 = read_graph(&inv.positional[0])?;
+            let g2 = read_graph(&inv.positional[1])?;
+            Output::Graph(diff_graph(&g1, &g2))
+        }
 
-            let g1 = parse_graph(&dot1).expect("Failed to parse first DOT file");
-            let g2 = parse_graph(&dot2).expect("Failed to parse second DOT file");
+        "fingerprint" => {
+            let graph = read_graph(&inv.positional[0])?;
+            if inv.format == Format::Dot {
+                Output::Graph(canonicalize(&graph))
+            } else {
+                Output::Fingerprint(fingerprint(&graph))
+            }
+        }
 
-            let delta = compute_delta(&g1, &g2);
-            let json = serde_json::to_string_pretty(&delta)
-                .expect("Failed to serialize GraphDelta to JSON");
-            println!("{}", json);
+        "isomorphic" => {
+            let g1 = read_graph(&inv.positional[0])?;
+            let g2 = read_graph(&inv.positional[1])?;
+            let mapping = find_isomorphism(&g1, &g2, &inv.isomorphism).map_err(CliError::Failed)?;
+            if mapping.is_none() {
+                exit_code = EXIT_FAILURE;
+            }
+            Output::Isomorphism(mapping)
         }
 
-        _ => {
-            eprintln!("Unknown command: {}", args[1]);
-            eprintln!("Usage:");
-            eprintln!("  {} parse <file.dot>", args[0]);
-            eprintln!("  {} delta <file1.dot> <file2.dot>", args[0]);
-            std::process::exit(1);
+        "query" => {
+            let graph = read_graph(&inv.positional[0])?;
+            let result = query_graph(&graph, &inv.positional[1])
+                .map_err(|e| CliError::Parse(format!("Error in query: {}", e)))?;
+            Output::Graph(result)
         }
-    }
+
+        "stats" => Output::Stats(graph_stats(&read_graph(&inv.positional[0])?)),
+
+        "path" => {
+            let mut graph = read_graph(&inv.positional[0])?;
+            if let Some(weights) = &inv.weights {
+                assign_weights(&mut graph, weights);
+            }
+            let limit = inv.limit.unwrap_or(DEFAULT_PATH_LIMIT);
+            let report = find_paths(&graph, &inv.positional[1], &inv.positional[2], limit)
+                .map_err(|e| CliError::Failed(format!("Error finding paths: {}", e)))?;
+            Output::Paths(report)
+        }
+
+        "centrality" => {
+            let mut graph = read_graph(&inv.positional[0])?;
+            compute_centrality(&mut graph);
+            Output::Graph(graph)
+        }
+
+        "lint" => {
+            let content = read_input(&inv.positional[0])?;
+            let options = LintOptions { roots: inv.roots.clone(), weights: inv.weights.clone().unwrap_or_default() };
+            let diagnostics = lint_dot(&content, &options)
+                .map_err(|e| CliError::Parse(format!("Error parsing {}: {}", input_name(&inv.positional[0]), e)))?;
+            let threshold = if inv.strict { Severity::Warning } else { Severity::Error };
+            if diagnostics.iter().any(|d| d.severity >= threshold) {
+                exit_code = EXIT_FAILURE;
+            }
+            Output::Diagnostics(diagnostics)
+        }
+
+        "check" => {
+            let graph = read_graph(&inv.positional[0])?;
+            let policy_text = read_input(&inv.positional[1])?;
+            let violations = parse_policy(&policy_text)
+                .and_then(|policy| check_policy(&graph, &policy))
+                .map_err(|e| CliError::Parse(format!("Error checking policy: {}", e)))?;
+            if !violations.is_empty() {
+                exit_code = EXIT_FAILURE;
+            }
+            Output::Violations(violations)
+        }
+
+        _ => unreachable!("command table and dispatch are out of sync"),
+    };
+
+    write_output(inv.output.as_deref(), &render(&output, inv.format)?)?;
+    Ok(exit_code)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args.first().map(String::as_str).unwrap_or("dot-delta");
+
+    let code = match run(program, &args[1..]) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{}", e);
+            e.exit_code()
+        }
+    };
+    std::process::exit(code);
 }
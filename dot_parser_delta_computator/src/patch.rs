@@ -0,0 +1,175 @@
+//! RFC 6902 JSON Patch for a `GraphDelta`, against the JSON layout of
+//! `GraphData` (`{"nodes": [...], "links": [...], "clusters": [...]}`).
+
+use crate::graph::{GraphData, GraphDelta};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    /// Fails the whole patch unless the document holds `value` at `path`.
+    Test { path: String, value: Value },
+}
+
+/// Indices of the elements to remove, highest first so earlier removals
+/// don't shift the later ones.
+fn removals<T>(items: &[T], removed: impl Fn(&T) -> bool) -> Vec<usize> {
+    let mut indices: Vec<usize> = items.iter().enumerate()
+        .filter(|(_, item)| removed(item))
+        .map(|(i, _)| i)
+        .collect();
+    indices.reverse();
+    indices
+}
+
+/// Build the patch turning `old`'s JSON into a document with the node ids and
+/// edges of `new`, given `delta = compute_delta(old, new)`. Like the delta,
+/// it leaves the attributes of nodes and links present in both untouched.
+///
+/// Cluster moves and removals address array indices of `old` and are each
+/// guarded by a `test` of the element's id (or source and target), so the
+/// patch fails cleanly on any other document. Added nodes, links and
+/// clusters are appended with their full JSON from `new`. `parse_graph`
+/// lists nodes by id and links in source order, so the indices match what
+/// `dot-delta parse` printed for `old`.
+///
+/// The delta must compare node ids as they are: a `compute_delta_mapped`
+/// delta leaves renamed nodes out, and the patch would not rename them.
+pub fn json_patch(old: &GraphData, new: &GraphData, delta: &GraphDelta) -> Vec<PatchOp> {
+    let removed_nodes: HashSet<&str> = delta.removed_nodes.iter().map(String::as_str).collect();
+    let added_nodes: HashSet<&str> = delta.added_nodes.iter().map(String::as_str).collect();
+    let removed_edges: HashSet<(&str, &str)> = delta.removed_edges.iter()
+        .map(|(a, b)| (a.as_str(), b.as_str()))
+        .collect();
+    let added_edges: HashSet<(&str, &str)> = delta.added_edges.iter()
+        .map(|(a, b)| (a.as_str(), b.as_str()))
+        .collect();
+
+    let moved: HashMap<&str, &Option<String>> = delta.moved_nodes.iter()
+        .map(|m| (m.node.as_str(), &m.to))
+        .collect();
+    let removed_clusters: HashSet<&str> = delta.removed_clusters.iter().map(String::as_str).collect();
+    let added_clusters: HashSet<&str> = delta.added_clusters.iter().map(String::as_str).collect();
+
+    let mut ops = Vec::new();
+
+    for (i, node) in old.nodes.iter().enumerate() {
+        if let Some(to) = moved.get(node.id.as_str()) {
+            ops.push(PatchOp::Test { path: format!("/nodes/{}/id", i), value: Value::from(node.id.as_str()) });
+            let path = format!("/nodes/{}/subgraph", i);
+            ops.push(match to {
+                Some(cluster) => PatchOp::Add { path, value: Value::from(cluster.as_str()) },
+                None => PatchOp::Remove { path },
+            });
+        }
+    }
+
+    for i in removals(&old.links, |l| removed_edges.contains(&(l.source.as_str(), l.target.as_str()))) {
+        let link = &old.links[i];
+        ops.push(PatchOp::Test { path: format!("/links/{}/source", i), value: Value::from(link.source.as_str()) });
+        ops.push(PatchOp::Test { path: format!("/links/{}/target", i), value: Value::from(link.target.as_str()) });
+        ops.push(PatchOp::Remove { path: format!("/links/{}", i) });
+    }
+
+    for i in removals(&old.nodes, |n| removed_nodes.contains(n.id.as_str())) {
+        ops.push(PatchOp::Test { path: format!("/nodes/{}/id", i), value: Value::from(old.nodes[i].id.as_str()) });
+        ops.push(PatchOp::Remove { path: format!("/nodes/{}", i) });
+    }
+
+    for i in removals(&old.clusters, |c| removed_clusters.contains(c.id.as_str())) {
+        ops.push(PatchOp::Test { path: format!("/clusters/{}/id", i), value: Value::from(old.clusters[i].id.as_str()) });
+        ops.push(PatchOp::Remove { path: format!("/clusters/{}", i) });
+    }
+
+    for cluster in new.clusters.iter().filter(|c| added_clusters.contains(c.id.as_str())) {
+        let value = serde_json::to_value(cluster).unwrap_or(Value::Null);
+        ops.push(PatchOp::Add { path: "/clusters/-".to_string(), value });
+    }
+
+    for node in new.nodes.iter().filter(|n| added_nodes.contains(n.id.as_str())) {
+        let value = serde_json::to_value(node).unwrap_or(Value::Null);
+        ops.push(PatchOp::Add { path: "/nodes/-".to_string(), value });
+    }
+
+    for link in new.links.iter().filter(|l| added_edges.contains(&(l.source.as_str(), l.target.as_str()))) {
+        let value = serde_json::to_value(link).unwrap_or(Value::Null);
+        ops.push(PatchOp::Add { path: "/links/-".to_string(), value });
+    }
+
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{compute_delta, parse_graph};
+
+    /// Apply `ops` to `doc` as RFC 6902 describes, for the ops `json_patch` emits.
+    fn apply(doc: &mut Value, ops: &[PatchOp]) -> Result<(), String> {
+        for op in ops {
+            let path = match op {
+                PatchOp::Test { path, value } => {
+                    if doc.pointer(path) != Some(value) {
+                        return Err(format!("test failed at {}", path));
+                    }
+                    continue;
+                }
+                PatchOp::Add { path, .. } | PatchOp::Remove { path } => path,
+            };
+            let (parent, key) = path.rsplit_once('/').ok_or_else(|| format!("bad path {}", path))?;
+            let target = doc.pointer_mut(parent).ok_or_else(|| format!("no parent for {}", path))?;
+            match (op, target) {
+                (PatchOp::Add { value, .. }, Value::Array(items)) if key == "-" => items.push(value.clone()),
+                (PatchOp::Add { value, .. }, Value::Object(fields)) => {
+                    fields.insert(key.to_string(), value.clone());
+                }
+                (PatchOp::Remove { .. }, Value::Array(items)) => {
+                    let i: usize = key.parse().map_err(|_| format!("bad index in {}", path))?;
+                    if i >= items.len() {
+                        return Err(format!("no element at {}", path));
+                    }
+                    items.remove(i);
+                }
+                (PatchOp::Remove { .. }, Value::Object(fields)) => {
+                    fields.remove(key).ok_or_else(|| format!("no field at {}", path))?;
+                }
+                _ => return Err(format!("unsupported op at {}", path)),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn patched_document_has_no_delta_to_new() {
+        let old = parse_graph("digraph { subgraph cluster_a { x; y } subgraph cluster_gone { g } z; x -> y; y -> z; z -> w }").unwrap();
+        let new = parse_graph("digraph { subgraph cluster_a { x } subgraph cluster_b { y; v } z; x -> y; y -> v; v -> x }").unwrap();
+        let ops = json_patch(&old, &new, &compute_delta(&old, &new));
+
+        let mut doc = serde_json::to_value(&old).unwrap();
+        apply(&mut doc, &ops).unwrap();
+        let patched: GraphData = serde_json::from_value(doc).unwrap();
+        let delta = compute_delta(&patched, &new);
+        assert!(delta.added_nodes.is_empty() && delta.removed_nodes.is_empty());
+        assert!(delta.added_edges.is_empty() && delta.removed_edges.is_empty());
+        assert!(delta.moved_nodes.is_empty());
+        assert!(delta.added_clusters.is_empty() && delta.removed_clusters.is_empty());
+        assert_eq!(patched.links.len(), new.links.len());
+    }
+
+    #[test]
+    fn patch_fails_on_another_document() {
+        let old = parse_graph("digraph { a -> b; b -> c }").unwrap();
+        let new = parse_graph("digraph { a -> b }").unwrap();
+        let ops = json_patch(&old, &new, &compute_delta(&old, &new));
+        assert!(ops.iter().any(|op| matches!(op, PatchOp::Test { .. })));
+
+        let mut other = old.clone();
+        other.nodes.reverse();
+        let mut doc = serde_json::to_value(&other).unwrap();
+        assert!(apply(&mut doc, &ops).is_err());
+    }
+}
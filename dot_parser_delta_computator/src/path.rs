@@ -0,0 +1,281 @@
+//! Reachability and path queries between two nodes of a `GraphData`.
+
+use crate::graph::{Adjacency, GraphData, GraphLink};
+use serde::{Serialize, Deserialize};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+/// Upper bound on the number of simple paths reported by `find_paths`.
+pub const DEFAULT_PATH_LIMIT: usize = 20;
+
+#[derive(Serialize, Deserialize)]
+pub struct WeightedPath {
+    pub cost: f64,
+    pub path: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PathReport {
+    pub from: String,
+    pub to: String,
+    pub reachable: bool,
+    /// Fewest-hops path, if any.
+    pub shortest: Option<Vec<String>>,
+    /// Cheapest path using the links' `weight`, if any.
+    pub weighted: Option<WeightedPath>,
+    /// Simple paths, up to the requested limit.
+    pub all_paths: Vec<Vec<String>>,
+}
+
+fn to_ids(adj: &Adjacency, path: &[usize]) -> Vec<String> {
+    path.iter().map(|&n| adj.ids[n].to_string()).collect()
+}
+
+/// Walk `prev` links back from `to` to rebuild the path.
+fn unwind(prev: &[usize], from: usize, to: usize) -> Vec<usize> {
+    let mut path = vec![to];
+    let mut n = to;
+    while n != from {
+        n = prev[n];
+        path.push(n);
+    }
+    path.reverse();
+    path
+}
+
+/// Cost of a link: its `weight`, or 1 when it has none.
+fn link_cost(link: &GraphLink) -> Result<f64, String> {
+    match link.weight {
+        None => Ok(1.0),
+        Some(w) if w >= 0.0 && w.is_finite() => Ok(w),
+        Some(w) => Err(format!("Invalid weight on edge {} -> {}: {}", link.source, link.target, w)),
+    }
+}
+
+/// Fewest-hops path from `from` to `to` (breadth-first search).
+pub fn shortest_path(graph: &GraphData, from: &str, to: &str) -> Result<Option<Vec<String>>, String> {
+    let adj = Adjacency::new(graph);
+    let (from, to) = (adj.find(from)?, adj.find(to)?);
+    let mut prev = vec![usize::MAX; adj.len()];
+    let mut queue = VecDeque::new();
+    prev[from] = from;
+    queue.push_back(from);
+
+    while let Some(n) = queue.pop_front() {
+        if n == to {
+            return Ok(Some(to_ids(&adj, &unwind(&prev, from, to))));
+        }
+        for &(m, _) in &adj.out[n] {
+            if prev[m] == usize::MAX {
+                prev[m] = n;
+                queue.push_back(m);
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+#[derive(PartialEq)]
+struct State {
+    cost: f64,
+    node: usize,
+}
+
+impl Eq for State {}
+
+impl Ord for State {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so that `BinaryHeap` pops the cheapest state first.
+        other.cost.partial_cmp(&self.cost).unwrap_or(Ordering::Equal)
+            .then_with(|| self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for State {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Cheapest path from `from` to `to` (Dijkstra over edge weights).
+pub fn weighted_shortest_path(graph: &GraphData, from: &str, to: &str) -> Result<Option<WeightedPath>, String> {
+    let adj = Adjacency::new(graph);
+    let (from, to) = (adj.find(from)?, adj.find(to)?);
+    let weights = graph.links.iter().map(link_cost).collect::<Result<Vec<_>, _>>()?;
+    let mut dist = vec![f64::INFINITY; adj.len()];
+    let mut prev = vec![usize::MAX; adj.len()];
+    let mut heap = BinaryHeap::new();
+    dist[from] = 0.0;
+    prev[from] = from;
+    heap.push(State { cost: 0.0, node: from });
+
+    while let Some(State { cost, node }) = heap.pop() {
+        if node == to {
+            return Ok(Some(WeightedPath { cost, path: to_ids(&adj, &unwind(&prev, from, to)) }));
+        }
+        if cost > dist[node] {
+            continue;
+        }
+        for &(m, l) in &adj.out[node] {
+            let next = cost + weights[l];
+            if next < dist[m] {
+                dist[m] = next;
+                prev[m] = node;
+                heap.push(State { cost: next, node: m });
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Nodes that can reach `to`, found by a breadth-first search over
+/// incoming links.
+fn reaching(adj: &Adjacency, to: usize) -> Vec<bool> {
+    let mut seen = vec![false; adj.len()];
+    let mut queue = VecDeque::new();
+    seen[to] = true;
+    queue.push_back(to);
+    while let Some(n) = queue.pop_front() {
+        for &(m, _) in &adj.inc[n] {
+            if !seen[m] {
+                seen[m] = true;
+                queue.push_back(m);
+            }
+        }
+    }
+    seen
+}
+
+/// Simple paths from `from` to `to`, stopping after `limit` paths. The search
+/// only enters nodes that can reach `to`, so dead ends are never explored.
+pub fn all_simple_paths(graph: &GraphData, from: &str, to: &str, limit: usize) -> Result<Vec<Vec<String>>, String> {
+    let adj = Adjacency::new(graph);
+    let (from, to) = (adj.find(from)?, adj.find(to)?);
+    let useful = reaching(&adj, to);
+    if limit == 0 || !useful[from] {
+        return Ok(Vec::new());
+    }
+    if from == to {
+        return Ok(vec![to_ids(&adj, &[from])]);
+    }
+
+    // Successors that can reach `to`, once each even with parallel links.
+    let succ: Vec<Vec<usize>> = adj.out.iter()
+        .map(|links| {
+            let mut next: Vec<usize> = Vec::new();
+            for &(m, _) in links {
+                if useful[m] && !next.contains(&m) {
+                    next.push(m);
+                }
+            }
+            next
+        })
+        .collect();
+
+    let mut paths = Vec::new();
+    let mut on_path = vec![false; adj.len()];
+    let mut path = vec![from];
+    let mut work = vec![0];
+    on_path[from] = true;
+
+    while let Some(pos) = work.last_mut() {
+        let v = *path.last().unwrap();
+        match succ[v].get(*pos) {
+            Some(&w) => {
+                *pos += 1;
+                if w == to {
+                    path.push(w);
+                    paths.push(to_ids(&adj, &path));
+                    path.pop();
+                    if paths.len() >= limit {
+                        break;
+                    }
+                } else if !on_path[w] {
+                    on_path[w] = true;
+                    path.push(w);
+                    work.push(0);
+                }
+            }
+            None => {
+                on_path[v] = false;
+                path.pop();
+                work.pop();
+            }
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Answer "can `from` reach `to`, and through what?" in one report.
+pub fn find_paths(graph: &GraphData, from: &str, to: &str, limit: usize) -> Result<PathReport, String> {
+    let shortest = shortest_path(graph, from, to)?;
+    let all_paths = match shortest {
+        Some(_) => all_simple_paths(graph, from, to, limit)?,
+        None => Vec::new(),
+    };
+    Ok(PathReport {
+        from: from.to_string(),
+        to: to.to_string(),
+        reachable: shortest.is_some(),
+        weighted: weighted_shortest_path(graph, from, to)?,
+        all_paths,
+        shortest,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::parse_graph;
+
+    /// `n` diamonds in a row: 2^n simple paths from `s` to `t`.
+    fn diamonds(n: usize) -> GraphData {
+        let mut dot = String::from("digraph { lonely; ");
+        for i in 0..n {
+            dot.push_str(&format!("m{i} -> a{i} -> m{j}; m{i} -> b{i} -> m{j}; a{i} -> dead; ", i = i, j = i + 1));
+        }
+        dot.push_str(&format!("s -> m0; m{} -> t; dead -> sink }}", n));
+        parse_graph(&dot).unwrap()
+    }
+
+    #[test]
+    fn unreachable_target_reports_no_paths() {
+        let graph = diamonds(60);
+        let report = find_paths(&graph, "s", "dead", 5).unwrap();
+        assert!(report.reachable);
+        // 2^60 paths lead away from `s`, none of them to `lonely`.
+        let report = find_paths(&graph, "s", "lonely", 5).unwrap();
+        assert!(!report.reachable);
+        assert!(report.shortest.is_none());
+        assert!(report.all_paths.is_empty());
+        assert!(all_simple_paths(&graph, "s", "lonely", 5).unwrap().is_empty());
+        assert_eq!(all_simple_paths(&graph, "s", "sink", 5).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn simple_paths_stop_at_the_limit() {
+        let graph = diamonds(60);
+        let paths = all_simple_paths(&graph, "s", "t", 7).unwrap();
+        assert_eq!(paths.len(), 7);
+        assert!(paths.iter().all(|p| p.first().unwrap() == "s" && p.last().unwrap() == "t"));
+        assert_eq!(all_simple_paths(&diamonds(3), "s", "t", 100).unwrap().len(), 8);
+    }
+
+    #[test]
+    fn parallel_links_give_one_path() {
+        let graph = parse_graph("digraph { a -> b; a -> b; b -> c }").unwrap();
+        assert_eq!(all_simple_paths(&graph, "a", "c", 10).unwrap(), vec![vec!["a", "b", "c"]]);
+    }
+
+    #[test]
+    fn weighted_path_prefers_cheap_links() {
+        let graph = parse_graph("digraph { a -> b [weight=5]; a -> c [weight=1]; c -> b [weight=1] }").unwrap();
+        let path = weighted_shortest_path(&graph, "a", "b").unwrap().unwrap();
+        assert_eq!(path.cost, 2.0);
+        assert_eq!(path.path, vec!["a", "c", "b"]);
+        assert_eq!(shortest_path(&graph, "a", "b").unwrap(), Some(vec!["a".to_string(), "b".to_string()]));
+    }
+}
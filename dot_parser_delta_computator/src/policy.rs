@@ -0,0 +1,254 @@
+//! Architecture rules checked against a `GraphData`.
+//!
+//! A policy is a JSON document whose node patterns are query expressions
+//! (see the `query` module), e.g.
+//!
+//! ```json
+//! {
+//!   "forbidden": [{ "from": "not id = LoggingApi", "to": "id = Logger",
+//!                   "via": "id = LoggingApi", "transitive": true }],
+//!   "required": [{ "from": "/Handler$/", "to": "id = Database", "transitive": true }],
+//!   "layers": ["/Manager$/", "/Router|Handler$/", "id = Database"],
+//!   "max_fan_out": 3
+//! }
+//! ```
+
+use crate::graph::{Adjacency, GraphData};
+use crate::query::{eval, parse_query};
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EdgeRule {
+    pub from: String,
+    pub to: String,
+    /// Nodes a path may go through to satisfy the rule: forbidden paths
+    /// through them are allowed, and only they count for required paths.
+    #[serde(default)]
+    pub via: Option<String>,
+    /// Whether the rule applies to paths rather than direct edges only.
+    #[serde(default)]
+    pub transitive: bool,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Policy {
+    #[serde(default)]
+    pub forbidden: Vec<EdgeRule>,
+    #[serde(default)]
+    pub required: Vec<EdgeRule>,
+    /// Layers from top to bottom; edges may only point to the same or a
+    /// lower layer. A node belongs to the first layer it matches.
+    #[serde(default)]
+    pub layers: Vec<String>,
+    #[serde(default)]
+    pub max_fan_out: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Violation {
+    /// `forbidden`, `required`, `layer` or `fan-out`.
+    pub rule: String,
+    pub message: String,
+    /// The offending path, or the offending node alone.
+    pub path: Vec<String>,
+}
+
+/// Parse a JSON policy document
+pub fn parse_policy(text: &str) -> Result<Policy, String> {
+    serde_json::from_str(text).map_err(|e| format!("Invalid policy: {}", e))
+}
+
+fn select(graph: &GraphData, adj: &Adjacency, pattern: &str) -> Result<Vec<bool>, String> {
+    let query = parse_query(pattern).map_err(|e| format!("In pattern '{}': {}", pattern, e))?;
+    eval(graph, adj, &query)
+}
+
+/// Shortest path from `start` to a node in `targets`, only passing through
+/// nodes allowed by `through`. Direct edges only unless `transitive`.
+fn search(adj: &Adjacency, start: usize, targets: &[bool], through: &dyn Fn(usize) -> bool, transitive: bool)
+    -> Option<Vec<usize>>
+{
+    let mut prev = vec![usize::MAX; adj.len()];
+    let mut queue = VecDeque::new();
+    prev[start] = start;
+    queue.push_back(start);
+
+    while let Some(n) = queue.pop_front() {
+        if n != start && !through(n) {
+            continue;
+        }
+        for &(m, _) in &adj.out[n] {
+            if targets[m] {
+                let mut path = vec![m, n];
+                let mut cur = n;
+                while cur != start {
+                    cur = prev[cur];
+                    path.push(cur);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if transitive && prev[m] == usize::MAX {
+                prev[m] = n;
+                queue.push_back(m);
+            }
+        }
+    }
+    None
+}
+
+/// Check a graph against a policy
+pub fn check_policy(graph: &GraphData, policy: &Policy) -> Result<Vec<Violation>, String> {
+    let adj = Adjacency::new(graph);
+    let ids = |path: &[usize]| path.iter().map(|&n| adj.ids[n].to_string()).collect::<Vec<_>>();
+    let mut violations = Vec::new();
+
+    for rule in &policy.forbidden {
+        let from = select(graph, &adj, &rule.from)?;
+        let to = select(graph, &adj, &rule.to)?;
+        let via = match &rule.via {
+            Some(p) => select(graph, &adj, p)?,
+            None => vec![false; adj.len()],
+        };
+        for start in (0..adj.len()).filter(|&n| from[n] && !via[n]) {
+            if let Some(path) = search(&adj, start, &to, &|n| !via[n], rule.transitive) {
+                let path = ids(&path);
+                violations.push(Violation {
+                    rule: "forbidden".to_string(),
+                    message: format!("{} must not depend on {} ({} -> {})",
+                        path[0], path[path.len() - 1], rule.from, rule.to),
+                    path,
+                });
+            }
+        }
+    }
+
+    for rule in &policy.required {
+        let from = select(graph, &adj, &rule.from)?;
+        let to = select(graph, &adj, &rule.to)?;
+        let via = match &rule.via {
+            Some(p) => Some(select(graph, &adj, p)?),
+            None => None,
+        };
+        let through = |n: usize| via.as_ref().is_none_or(|v| v[n]);
+        for start in (0..adj.len()).filter(|&n| from[n]) {
+            if search(&adj, start, &to, &through, rule.transitive).is_none() {
+                violations.push(Violation {
+                    rule: "required".to_string(),
+                    message: format!("{} must depend on {}", adj.ids[start], rule.to),
+                    path: vec![adj.ids[start].to_string()],
+                });
+            }
+        }
+    }
+
+    if !policy.layers.is_empty() {
+        let mut layer = vec![usize::MAX; adj.len()];
+        for (i, pattern) in policy.layers.iter().enumerate() {
+            for (n, matched) in select(graph, &adj, pattern)?.into_iter().enumerate() {
+                if matched && layer[n] == usize::MAX {
+                    layer[n] = i;
+                }
+            }
+        }
+        for (n, out) in adj.out.iter().enumerate() {
+            for &(m, _) in out {
+                if layer[n] != usize::MAX && layer[m] != usize::MAX && layer[m] < layer[n] {
+                    violations.push(Violation {
+                        rule: "layer".to_string(),
+                        message: format!("{} (layer {}) depends on {} in higher layer {}",
+                            adj.ids[n], layer[n], adj.ids[m], layer[m]),
+                        path: ids(&[n, m]),
+                    });
+                }
+            }
+        }
+    }
+
+    if let Some(max) = policy.max_fan_out {
+        for (n, out) in adj.out.iter().enumerate() {
+            if out.len() > max {
+                violations.push(Violation {
+                    rule: "fan-out".to_string(),
+                    message: format!("{} has fan-out {} (max {})", adj.ids[n], out.len(), max),
+                    path: vec![adj.ids[n].to_string()],
+                });
+            }
+        }
+    }
+
+    Ok(violations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::parse_graph;
+
+    const GRAPH: &str = "digraph {
+        UserManager -> MessageRouter -> ChatHandler -> LoggingApi -> Logger;
+        ChatHandler -> Database; UserManager -> Database
+    }";
+
+    fn check(policy: &str) -> Vec<(String, Vec<String>)> {
+        let graph = parse_graph(GRAPH).unwrap();
+        check_policy(&graph, &parse_policy(policy).unwrap()).unwrap()
+            .into_iter()
+            .map(|v| (v.rule, v.path))
+            .collect()
+    }
+
+    fn path(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn forbidden_rules() {
+        let direct = r#"{ "forbidden": [{ "from": "/Handler$/", "to": "id = Logger" }] }"#;
+        assert!(check(direct).is_empty());
+        let transitive = r#"{ "forbidden": [{ "from": "/Handler$/", "to": "id = Logger", "transitive": true }] }"#;
+        assert_eq!(check(transitive), vec![("forbidden".to_string(), path(&["ChatHandler", "LoggingApi", "Logger"]))]);
+        let via = r#"{ "forbidden": [{ "from": "not id = LoggingApi", "to": "id = Logger",
+                                       "via": "id = LoggingApi", "transitive": true }] }"#;
+        assert!(check(via).is_empty());
+    }
+
+    #[test]
+    fn required_rules() {
+        let direct = r#"{ "required": [{ "from": "/Manager$|Handler$/", "to": "id = Database" }] }"#;
+        assert!(check(direct).is_empty());
+        let missing = r#"{ "required": [{ "from": "/Router$/", "to": "id = Database" }] }"#;
+        assert_eq!(check(missing), vec![("required".to_string(), path(&["MessageRouter"]))]);
+        let transitive = r#"{ "required": [{ "from": "/Router$/", "to": "id = Logger", "transitive": true }] }"#;
+        assert!(check(transitive).is_empty());
+        let via = r#"{ "required": [{ "from": "/Router$/", "to": "id = Logger", "via": "/Handler$/", "transitive": true }] }"#;
+        assert_eq!(check(via), vec![("required".to_string(), path(&["MessageRouter"]))]);
+    }
+
+    #[test]
+    fn layer_rules() {
+        let ordered = r#"{ "layers": ["/Manager$/", "/Router$|Handler$/", "id = Database"] }"#;
+        assert!(check(ordered).is_empty());
+        let inverted = r#"{ "layers": ["id = Database", "/Router$|Handler$/"] }"#;
+        assert_eq!(check(inverted), vec![("layer".to_string(), path(&["ChatHandler", "Database"]))]);
+    }
+
+    #[test]
+    fn fan_out_rules() {
+        assert!(check(r#"{ "max_fan_out": 2 }"#).is_empty());
+        assert_eq!(check(r#"{ "max_fan_out": 1 }"#), vec![
+            ("fan-out".to_string(), path(&["ChatHandler"])),
+            ("fan-out".to_string(), path(&["UserManager"])),
+        ]);
+    }
+
+    #[test]
+    fn malformed_policies_are_rejected() {
+        assert!(parse_policy(r#"{ "max_fan_out": "three" }"#).err().unwrap().starts_with("Invalid policy: "));
+        assert!(parse_policy(r#"{ "forbidden": [{ "from": "a" }] }"#).err().unwrap().contains("missing field `to`"));
+        let graph = parse_graph(GRAPH).unwrap();
+        let policy = parse_policy(r#"{ "layers": ["near(Logger)"] }"#).unwrap();
+        assert_eq!(check_policy(&graph, &policy).unwrap_err(), "In pattern 'near(Logger)': Expected Comma, found RParen");
+    }
+}
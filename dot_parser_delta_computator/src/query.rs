@@ -0,0 +1,498 @@
+//! A small query language for selecting parts of a `GraphData`.
+//!
+//! ```text
+//! expr  := term ( "or" term )*
+//! term  := unary ( "and" unary )*
+//! unary := "not" unary | atom
+//! atom  := "(" expr ")"
+//!        | /regex/                          id matches regex
+//!        | "id" op value | "label" op value
+//!        | "[" key ( op value )? "]"         attribute exists / compares
+//!        | "near" "(" node "," hops ")"      k-hop neighbourhood, any direction
+//!        | "upstream" "(" node ")"           node and everything that reaches it
+//!        | "downstream" "(" node ")"         node and everything it reaches
+//! op    := "=" | "!=" | "~" | "<" | "<=" | ">" | ">="
+//! ```
+//!
+//! `~` expects a `/regex/`; `<`, `>` and friends compare numerically.
+//! The result keeps the matching nodes and every link between two of them,
+//! e.g. `near(MessageRouter, 2)` or `/Handler$/ or [shape=box]`.
+
+use crate::graph::{unquote, Adjacency, GraphData, GraphNode};
+use regex::Regex;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    Cmp(CmpOp, String),
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone)]
+pub enum Query {
+    Id(Matcher),
+    Label(Matcher),
+    /// Attribute predicate; `None` only checks that the attribute is present.
+    Attr(String, Option<Matcher>),
+    Near(String, usize),
+    Upstream(String),
+    Downstream(String),
+    Not(Box<Query>),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Op(String),
+    Regex(String),
+    Str(String),
+    Word(String),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' => { tokens.push(Token::LParen); i += 1; }
+            ')' => { tokens.push(Token::RParen); i += 1; }
+            '[' => { tokens.push(Token::LBracket); i += 1; }
+            ']' => { tokens.push(Token::RBracket); i += 1; }
+            ',' => { tokens.push(Token::Comma); i += 1; }
+            '/' | '"' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(format!("Unterminated {} in query", c)),
+                        Some('\\') if chars.get(i + 1) == Some(&c) => {
+                            text.push(c);
+                            i += 2;
+                        }
+                        Some(&ch) if ch == c => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&ch) => {
+                            text.push(ch);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push(if c == '/' { Token::Regex(text) } else { Token::Str(text) });
+            }
+            '=' | '~' => { tokens.push(Token::Op(c.to_string())); i += 1; }
+            '!' | '<' | '>' => {
+                if chars.get(i + 1) == Some(&'=') {
+                    tokens.push(Token::Op(format!("{}=", c)));
+                    i += 2;
+                } else if c == '!' {
+                    return Err("Expected '!=' in query".to_string());
+                } else {
+                    tokens.push(Token::Op(c.to_string()));
+                    i += 1;
+                }
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '-' || c == '.' => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '-' | '.'))
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Word(chars[start..i].iter().collect()));
+            }
+            other => return Err(format!("Unexpected character '{}' in query", other)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(ref t) if *t == expected => Ok(()),
+            Some(t) => Err(format!("Expected {:?}, found {:?}", expected, t)),
+            None => Err(format!("Expected {:?}, found end of query", expected)),
+        }
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn expr(&mut self) -> Result<Query, String> {
+        let mut lhs = self.term()?;
+        while self.peek_keyword("or") {
+            self.pos += 1;
+            lhs = Query::Or(Box::new(lhs), Box::new(self.term()?));
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<Query, String> {
+        let mut lhs = self.unary()?;
+        while self.peek_keyword("and") {
+            self.pos += 1;
+            lhs = Query::And(Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Query, String> {
+        if self.peek_keyword("not") {
+            self.pos += 1;
+            return Ok(Query::Not(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Query, String> {
+        match self.next() {
+            Some(Token::LParen) => {
+                let q = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(q)
+            }
+            Some(Token::Regex(re)) => Ok(Query::Id(Matcher::Regex(compile(&re)?))),
+            Some(Token::LBracket) => {
+                let key = self.value()?;
+                let matcher = if let Some(Token::Op(_)) = self.peek() {
+                    Some(self.matcher()?)
+                } else {
+                    None
+                };
+                self.expect(Token::RBracket)?;
+                Ok(Query::Attr(key, matcher))
+            }
+            Some(Token::Word(w)) => match w.to_ascii_lowercase().as_str() {
+                "id" => Ok(Query::Id(self.matcher()?)),
+                "label" => Ok(Query::Label(self.matcher()?)),
+                "near" => {
+                    self.expect(Token::LParen)?;
+                    let node = self.value()?;
+                    self.expect(Token::Comma)?;
+                    let hops = self.value()?;
+                    let hops = hops.parse()
+                        .map_err(|_| format!("Invalid hop count: {}", hops))?;
+                    self.expect(Token::RParen)?;
+                    Ok(Query::Near(node, hops))
+                }
+                "upstream" | "downstream" => {
+                    self.expect(Token::LParen)?;
+                    let node = self.value()?;
+                    self.expect(Token::RParen)?;
+                    if w.eq_ignore_ascii_case("upstream") {
+                        Ok(Query::Upstream(node))
+                    } else {
+                        Ok(Query::Downstream(node))
+                    }
+                }
+                _ => Err(format!("Unknown query term: {}", w)),
+            },
+            Some(t) => Err(format!("Unexpected {:?} in query", t)),
+            None => Err("Unexpected end of query".to_string()),
+        }
+    }
+
+    fn value(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Word(w)) => Ok(w),
+            Some(Token::Str(s)) => Ok(s),
+            Some(t) => Err(format!("Expected a value, found {:?}", t)),
+            None => Err("Expected a value, found end of query".to_string()),
+        }
+    }
+
+    fn matcher(&mut self) -> Result<Matcher, String> {
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            Some(t) => return Err(format!("Expected an operator, found {:?}", t)),
+            None => return Err("Expected an operator, found end of query".to_string()),
+        };
+        if op == "~" {
+            return match self.next() {
+                Some(Token::Regex(re)) => Ok(Matcher::Regex(compile(&re)?)),
+                _ => Err("Expected /regex/ after '~'".to_string()),
+            };
+        }
+        let op = match op.as_str() {
+            "=" => CmpOp::Eq,
+            "!=" => CmpOp::Ne,
+            "<" => CmpOp::Lt,
+            "<=" => CmpOp::Le,
+            ">" => CmpOp::Gt,
+            _ => CmpOp::Ge,
+        };
+        Ok(Matcher::Cmp(op, self.value()?))
+    }
+}
+
+fn compile(re: &str) -> Result<Regex, String> {
+    Regex::new(re).map_err(|e| format!("Invalid regex /{}/: {}", re, e))
+}
+
+/// Parse a query string into a `Query`
+pub fn parse_query(input: &str) -> Result<Query, String> {
+    let mut parser = Parser { tokens: tokenize(input)?, pos: 0 };
+    let query = parser.expr()?;
+    match parser.peek() {
+        None => Ok(query),
+        Some(t) => Err(format!("Unexpected {:?} after end of query", t)),
+    }
+}
+
+impl Matcher {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Matcher::Regex(re) => re.is_match(value),
+            Matcher::Cmp(CmpOp::Eq, v) => value == v,
+            Matcher::Cmp(CmpOp::Ne, v) => value != v,
+            Matcher::Cmp(op, v) => match (value.parse::<f64>(), v.parse::<f64>()) {
+                (Ok(a), Ok(b)) => match op {
+                    CmpOp::Lt => a < b,
+                    CmpOp::Le => a <= b,
+                    CmpOp::Gt => a > b,
+                    _ => a >= b,
+                },
+                _ => false,
+            },
+        }
+    }
+}
+
+/// The label of a node, falling back to its name when none is declared.
+pub(crate) fn node_label(node: &GraphNode) -> String {
+    node.attributes.get("label").cloned().unwrap_or_else(|| unquote(&node.name))
+}
+
+/// Breadth-first search from `start`, up to `max_hops` hops.
+fn reach<'a, F, I>(adj: &'a Adjacency, start: usize, max_hops: usize, next: F) -> Vec<bool>
+where
+    F: Fn(&'a Adjacency, usize) -> I,
+    I: Iterator<Item = usize> + 'a,
+{
+    let mut seen = vec![false; adj.len()];
+    let mut queue = VecDeque::new();
+    seen[start] = true;
+    queue.push_back((start, 0));
+
+    while let Some((n, hops)) = queue.pop_front() {
+        if hops == max_hops {
+            continue;
+        }
+        for m in next(adj, n) {
+            if !seen[m] {
+                seen[m] = true;
+                queue.push_back((m, hops + 1));
+            }
+        }
+    }
+    seen
+}
+
+/// Evaluate a query to one flag per node of `graph`.
+pub(crate) fn eval(graph: &GraphData, adj: &Adjacency, query: &Query) -> Result<Vec<bool>, String> {
+    let nodes = &graph.nodes;
+    Ok(match query {
+        Query::Id(m) => nodes.iter().map(|n| m.matches(&unquote(&n.id))).collect(),
+        Query::Label(m) => nodes.iter().map(|n| m.matches(&node_label(n))).collect(),
+        Query::Attr(key, m) => nodes.iter()
+            .map(|n| match (n.attributes.get(key), m) {
+                (Some(v), Some(m)) => m.matches(v),
+                (Some(_), None) => true,
+                (None, _) => false,
+            })
+            .collect(),
+        Query::Near(id, hops) => reach(adj, adj.find(id)?, *hops, |a, n| a.neighbors(n)),
+        Query::Upstream(id) => reach(adj, adj.find(id)?, usize::MAX, |a, n| {
+            a.inc[n].iter().map(|&(m, _)| m)
+        }),
+        Query::Downstream(id) => reach(adj, adj.find(id)?, usize::MAX, |a, n| {
+            a.out[n].iter().map(|&(m, _)| m)
+        }),
+        Query::Not(q) => eval(graph, adj, q)?.into_iter().map(|b| !b).collect(),
+        Query::And(a, b) => eval(graph, adj, a)?.into_iter()
+            .zip(eval(graph, adj, b)?)
+            .map(|(x, y)| x && y)
+            .collect(),
+        Query::Or(a, b) => eval(graph, adj, a)?.into_iter()
+            .zip(eval(graph, adj, b)?)
+            .map(|(x, y)| x || y)
+            .collect(),
+    })
+}
+
+/// Keep only the given nodes, the links between them and the clusters
+/// holding them, with the clusters those are nested in.
+pub(crate) fn induced_subgraph(graph: &GraphData, keep: &HashSet<&str>) -> GraphData {
+    let parents: HashMap<&str, Option<&str>> = graph.clusters.iter()
+        .map(|c| (c.id.as_str(), c.parent.as_deref()))
+        .collect();
+    let mut used: HashSet<&str> = HashSet::new();
+    for node in graph.nodes.iter().filter(|n| keep.contains(n.id.as_str())) {
+        let mut cluster = node.subgraph.as_deref();
+        while let Some(c) = cluster.filter(|c| used.insert(c)) {
+            cluster = parents.get(c).copied().flatten();
+        }
+    }
+
+    GraphData {
+        nodes: graph.nodes.iter().filter(|n| keep.contains(n.id.as_str())).cloned().collect(),
+        links: graph.links.iter()
+            .filter(|l| keep.contains(l.source.as_str()) && keep.contains(l.target.as_str()))
+            .cloned()
+            .collect(),
+        clusters: graph.clusters.iter().filter(|c| used.contains(c.id.as_str())).cloned().collect(),
+        directed: graph.directed,
+        attributes: graph.attributes.clone(),
+        html_attributes: graph.html_attributes.clone(),
+    }
+}
+
+/// Run a parsed query against a graph
+pub fn run_query(graph: &GraphData, query: &Query) -> Result<GraphData, String> {
+    let adj = Adjacency::new(graph);
+    let selected = eval(graph, &adj, query)?;
+    let keep: HashSet<&str> = graph.nodes.iter()
+        .zip(selected)
+        .filter(|(_, keep)| *keep)
+        .map(|(n, _)| n.id.as_str())
+        .collect();
+    Ok(induced_subgraph(graph, &keep))
+}
+
+/// Parse and run a query string against a graph
+pub fn query_graph(graph: &GraphData, query: &str) -> Result<GraphData, String> {
+    run_query(graph, &parse_query(query)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::parse_graph;
+
+    const GRAPH: &str = "digraph {
+        subgraph cluster_api { Router [shape=box, label=\"Message Router\"]; subgraph cluster_auth { Auth [shape=box] } }
+        subgraph cluster_store { Database [size=10] }
+        Client [size=2]; Client -> Router -> Handler; Router -> Auth; Handler -> Database [weight=3]; Logger
+    }";
+
+    fn ids(query: &str) -> Vec<String> {
+        let graph = parse_graph(GRAPH).unwrap();
+        query_graph(&graph, query).unwrap().nodes.into_iter().map(|n| n.id).collect()
+    }
+
+    fn error(query: &str) -> String {
+        match parse_query(query) {
+            Ok(q) => panic!("{} parsed as {:?}", query, q),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn tokenizer_reads_strings_regexes_and_operators() {
+        let tokens = tokenize(r#"[size>=2] and id != "a \"b\"" or /x\/y/"#).unwrap();
+        assert_eq!(tokens, vec![
+            Token::LBracket, Token::Word("size".to_string()), Token::Op(">=".to_string()), Token::Word("2".to_string()), Token::RBracket,
+            Token::Word("and".to_string()), Token::Word("id".to_string()), Token::Op("!=".to_string()), Token::Str("a \"b\"".to_string()),
+            Token::Word("or".to_string()), Token::Regex("x/y".to_string()),
+        ]);
+        assert_eq!(tokenize("id = \"open").unwrap_err(), "Unterminated \" in query");
+        assert_eq!(tokenize("a ! b").unwrap_err(), "Expected '!=' in query");
+        assert_eq!(tokenize("a & b").unwrap_err(), "Unexpected character '&' in query");
+    }
+
+    #[test]
+    fn parser_reports_malformed_queries() {
+        assert_eq!(error("near(Router)"), "Expected Comma, found RParen");
+        assert_eq!(error("near(Router, two)"), "Invalid hop count: two");
+        assert_eq!(error("(id = a"), "Expected RParen, found end of query");
+        assert_eq!(error("id ~ a"), "Expected /regex/ after '~'");
+        assert_eq!(error("label a"), "Expected an operator, found Word(\"a\")");
+        assert_eq!(error("color = red"), "Unknown query term: color");
+        assert_eq!(error("id = a b"), "Unexpected Word(\"b\") after end of query");
+        assert!(error("/(/").starts_with("Invalid regex /(/"));
+        assert_eq!(error(""), "Unexpected end of query");
+    }
+
+    #[test]
+    fn attributes_compare_as_text_or_numbers() {
+        assert_eq!(ids("[shape=box]"), vec!["Auth", "Router"]);
+        assert_eq!(ids("[size]"), vec!["Client", "Database"]);
+        assert_eq!(ids("[size > 5]"), vec!["Database"]);
+        assert_eq!(ids("[size <= 2] or [shape > 1]"), vec!["Client"]);
+        assert_eq!(ids("label = \"Message Router\""), vec!["Router"]);
+        assert_eq!(ids("label = Logger"), vec!["Logger"]);
+        assert_eq!(ids("not [shape] and id != Logger and id != Client"), vec!["Database", "Handler"]);
+    }
+
+    #[test]
+    fn regexes_match_ids_and_labels() {
+        assert_eq!(ids("/er$/"), vec!["Handler", "Logger", "Router"]);
+        assert_eq!(ids("label ~ /^Message/"), vec!["Router"]);
+        assert_eq!(ids("/^(Auth|Client)$/ or id ~ /base/"), vec!["Auth", "Client", "Database"]);
+    }
+
+    #[test]
+    fn neighbourhoods_follow_links() {
+        assert_eq!(ids("near(Router, 1)"), vec!["Auth", "Client", "Handler", "Router"]);
+        assert_eq!(ids("near(Client, 0)"), vec!["Client"]);
+        assert_eq!(ids("upstream(Handler)"), vec!["Client", "Handler", "Router"]);
+        assert_eq!(ids("downstream(Router)"), vec!["Auth", "Database", "Handler", "Router"]);
+        assert_eq!(ids("downstream(Logger)"), vec!["Logger"]);
+        let graph = parse_graph(GRAPH).unwrap();
+        assert!(query_graph(&graph, "upstream(Nowhere)").is_err());
+    }
+
+    #[test]
+    fn subgraphs_keep_links_and_clusters_of_kept_nodes() {
+        let graph = parse_graph(GRAPH).unwrap();
+        let result = query_graph(&graph, "id = Auth or id = Router or id = Handler").unwrap();
+        let edges: Vec<(&str, &str)> = result.links.iter().map(|l| (l.source.as_str(), l.target.as_str())).collect();
+        assert_eq!(edges, vec![("Router", "Handler"), ("Router", "Auth")]);
+        let clusters: Vec<&str> = result.clusters.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(clusters, vec!["cluster_api", "cluster_auth"]);
+
+        // A nested cluster brings its parent along.
+        let result = query_graph(&graph, "id = Auth").unwrap();
+        assert_eq!(result.clusters.len(), 2);
+        assert!(query_graph(&graph, "id = Logger").unwrap().clusters.is_empty());
+    }
+}
@@ -0,0 +1,455 @@
+//! Visual diffs: the union of two graphs with every node and edge styled by
+//! whether it was added, removed or kept, and a dependency-free SVG renderer
+//! with a layered layout for attaching the result to reviews.
+
+use crate::graph::{write_dot, Adjacency, GraphCluster, GraphData, GraphLink, GraphNode};
+use crate::label::{link_label, node_label, Justify, LabelLine};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const ADDED: (&str, &str) = ("added", "green");
+const REMOVED: (&str, &str) = ("removed", "red");
+const UNCHANGED: (&str, &str) = ("unchanged", "grey");
+
+const FONT_SIZE: f64 = 12.0;
+const CHAR_WIDTH: f64 = 7.0;
+const LINE_HEIGHT: f64 = 15.0;
+const NODE_GAP: f64 = 30.0;
+const LAYER_GAP: f64 = 60.0;
+const MARGIN: f64 = 20.0;
+
+fn mark(attributes: &mut BTreeMap<String, String>, (status, color): (&str, &str)) {
+    attributes.insert("diff".to_string(), status.to_string());
+    attributes.insert("color".to_string(), color.to_string());
+    attributes.insert("fontcolor".to_string(), color.to_string());
+    if status == REMOVED.0 {
+        attributes.insert("style".to_string(), "dashed".to_string());
+    }
+}
+
+/// Merge `old` and `new` into one graph, sorted by id. Each node and edge gets
+/// a `diff` attribute (`added`, `removed` or `unchanged`) and a matching
+/// `color`/`fontcolor`: green, red (and dashed) or grey. Attributes come from
+/// `new` where the element still exists. Parallel edges are matched up in
+/// order, so going from two `a -> b` edges to three shows one added edge.
+pub fn diff_graph(old: &GraphData, new: &GraphData) -> GraphData {
+    let old_nodes: HashSet<&str> = old.nodes.iter().map(|n| n.id.as_str()).collect();
+    let new_nodes: HashSet<&str> = new.nodes.iter().map(|n| n.id.as_str()).collect();
+    let count = |graph: &GraphData| {
+        let mut count: HashMap<(String, String), usize> = HashMap::new();
+        for l in &graph.links {
+            *count.entry((l.source.clone(), l.target.clone())).or_insert(0) += 1;
+        }
+        count
+    };
+    let (old_edges, new_edges) = (count(old), count(new));
+
+    let mut nodes: BTreeMap<&str, GraphNode> = BTreeMap::new();
+    for node in new.nodes.iter().chain(&old.nodes) {
+        nodes.entry(node.id.as_str()).or_insert_with(|| {
+            let mut node = node.clone();
+            let status = match (old_nodes.contains(node.id.as_str()), new_nodes.contains(node.id.as_str())) {
+                (false, _) => ADDED,
+                (_, false) => REMOVED,
+                _ => UNCHANGED,
+            };
+            mark(&mut node.attributes, status);
+            node
+        });
+    }
+
+    // The i-th `a -> b` of `new` is unchanged if `old` has more than i of
+    // them, else added; the ones `old` has beyond those of `new` are removed.
+    let mut links: Vec<GraphLink> = Vec::new();
+    let mut seen: HashMap<(String, String), usize> = HashMap::new();
+    for link in &new.links {
+        let key = (link.source.clone(), link.target.clone());
+        let i = seen.entry(key.clone()).or_insert(0);
+        let mut link = link.clone();
+        mark(&mut link.attributes, if *i < old_edges.get(&key).copied().unwrap_or(0) { UNCHANGED } else { ADDED });
+        *i += 1;
+        links.push(link);
+    }
+    seen.clear();
+    for link in &old.links {
+        let key = (link.source.clone(), link.target.clone());
+        let i = seen.entry(key.clone()).or_insert(0);
+        if *i >= new_edges.get(&key).copied().unwrap_or(0) {
+            let mut link = link.clone();
+            mark(&mut link.attributes, REMOVED);
+            links.push(link);
+        }
+        *i += 1;
+    }
+    links.sort_by(|a, b| (&a.source, &a.target).cmp(&(&b.source, &b.target)));
+
+    let mut clusters: Vec<GraphCluster> = Vec::new();
+    for cluster in new.clusters.iter().chain(&old.clusters) {
+        if !clusters.iter().any(|c| c.id == cluster.id) {
+            clusters.push(cluster.clone());
+        }
+    }
+
+    GraphData {
+        nodes: nodes.into_values().collect(),
+        links,
+        clusters,
+        directed: new.directed,
+        attributes: new.attributes.clone(),
+        html_attributes: new.html_attributes.clone(),
+    }
+}
+
+/// The merged diff graph of `old` and `new`, as DOT.
+pub fn diff_dot(old: &GraphData, new: &GraphData) -> String {
+    write_dot(&diff_graph(old, new))
+}
+
+/// Non-empty lines of a node's decoded label.
+fn label_lines(node: &GraphNode) -> Vec<LabelLine> {
+    let label = node.display_label.clone().unwrap_or_else(|| node_label(node, None));
+    label.lines.into_iter().filter(|line| !line.text().is_empty()).collect()
+}
+
+/// A label line as `<tspan>`s, one per styled run.
+fn line_spans(line: &LabelLine) -> String {
+    line.runs.iter()
+        .map(|run| {
+            let style = &run.style;
+            let mut attrs = String::new();
+            if style.bold {
+                attrs.push_str(" font-weight=\"bold\"");
+            }
+            if style.italic {
+                attrs.push_str(" font-style=\"italic\"");
+            }
+            if style.underline || style.strike {
+                let decoration = if style.underline { "underline" } else { "line-through" };
+                attrs.push_str(&format!(" text-decoration=\"{}\"", decoration));
+            }
+            if let Some(color) = &style.color {
+                attrs.push_str(&format!(" fill=\"{}\"", escape_xml(color)));
+            }
+            if let Some(face) = &style.face {
+                attrs.push_str(&format!(" font-family=\"{}\"", escape_xml(face)));
+            }
+            if attrs.is_empty() {
+                escape_xml(&run.text)
+            } else {
+                format!("<tspan{}>{}</tspan>", attrs, escape_xml(&run.text))
+            }
+        })
+        .collect()
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Layer of each node: longest path from a source, ignoring the edges that
+/// close cycles (found by a depth-first search in id order).
+fn layers(adj: &Adjacency) -> Vec<usize> {
+    let n = adj.len();
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by_key(|&v| adj.ids[v]);
+
+    // 0 = unvisited, 1 = on the stack, 2 = done
+    let mut state = vec![0u8; n];
+    let mut finished = Vec::with_capacity(n);
+    let mut back_edges: HashSet<(usize, usize)> = HashSet::new();
+    for &root in &order {
+        if state[root] != 0 {
+            continue;
+        }
+        let mut stack = vec![(root, 0)];
+        state[root] = 1;
+        while let Some(&mut (v, ref mut next)) = stack.last_mut() {
+            if let Some(&(w, _)) = adj.out[v].get(*next) {
+                *next += 1;
+                match state[w] {
+                    0 => {
+                        state[w] = 1;
+                        stack.push((w, 0));
+                    }
+                    1 => {
+                        back_edges.insert((v, w));
+                    }
+                    _ => {}
+                }
+            } else {
+                state[v] = 2;
+                finished.push(v);
+                stack.pop();
+            }
+        }
+    }
+
+    // Reverse finishing order is a topological order of the remaining edges.
+    let mut layer = vec![0; n];
+    for &v in finished.iter().rev() {
+        for &(w, _) in &adj.out[v] {
+            if w != v && !back_edges.contains(&(v, w)) {
+                layer[w] = layer[w].max(layer[v] + 1);
+            }
+        }
+    }
+    layer
+}
+
+struct Placed {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+/// Point where the segment from the centre of `box_` towards `(tx, ty)`
+/// leaves the box.
+fn clip(box_: &Placed, tx: f64, ty: f64) -> (f64, f64) {
+    let (dx, dy) = (tx - box_.x, ty - box_.y);
+    if dx == 0.0 && dy == 0.0 {
+        return (box_.x, box_.y);
+    }
+    let sx = if dx != 0.0 { box_.width / 2.0 / dx.abs() } else { f64::INFINITY };
+    let sy = if dy != 0.0 { box_.height / 2.0 / dy.abs() } else { f64::INFINITY };
+    let s = sx.min(sy).min(1.0);
+    (box_.x + dx * s, box_.y + dy * s)
+}
+
+fn attr<'a>(attributes: &'a BTreeMap<String, String>, key: &str, default: &'a str) -> &'a str {
+    attributes.get(key).map(String::as_str).unwrap_or(default)
+}
+
+fn dash(attributes: &BTreeMap<String, String>) -> &'static str {
+    if attr(attributes, "style", "").split(',').any(|s| s.trim() == "dashed") {
+        " stroke-dasharray=\"6,4\""
+    } else {
+        ""
+    }
+}
+
+/// Render a graph as a self-contained SVG, with nodes placed in layers along
+/// edge direction. Honours the `label`, `color`, `fontcolor` and `style=dashed`
+/// attributes of nodes and edges.
+pub fn render_svg(graph: &GraphData) -> String {
+    let adj = Adjacency::new(graph);
+    let layer = layers(&adj);
+    let lines: Vec<Vec<LabelLine>> = graph.nodes.iter().map(label_lines).collect();
+
+    // Group nodes by layer, sorted by id, then order each layer by the mean
+    // position of its predecessors to reduce crossings.
+    let depth = layer.iter().copied().max().map_or(0, |d| d + 1);
+    let mut rows: Vec<Vec<usize>> = vec![Vec::new(); depth];
+    for v in 0..adj.len() {
+        rows[layer[v]].push(v);
+    }
+    let mut slot = vec![0.0; adj.len()];
+    for row in rows.iter_mut() {
+        row.sort_by_key(|&v| adj.ids[v]);
+        let key: HashMap<usize, f64> = row.iter().enumerate()
+            .map(|(i, &v)| {
+                let preds: Vec<f64> = adj.inc[v].iter()
+                    .filter(|&&(u, _)| layer[u] < layer[v])
+                    .map(|&(u, _)| slot[u])
+                    .collect();
+                let k = if preds.is_empty() { i as f64 } else { preds.iter().sum::<f64>() / preds.len() as f64 };
+                (v, k)
+            })
+            .collect();
+        row.sort_by(|a, b| key[a].partial_cmp(&key[b]).unwrap_or(std::cmp::Ordering::Equal));
+        for (i, &v) in row.iter().enumerate() {
+            slot[v] = i as f64;
+        }
+    }
+
+    let mut placed: Vec<Placed> = lines.iter()
+        .map(|l| {
+            let chars = l.iter().map(|line| line.text().chars().count()).max().unwrap_or(0);
+            Placed {
+                x: 0.0,
+                y: 0.0,
+                width: chars as f64 * CHAR_WIDTH + 20.0,
+                height: l.len().max(1) as f64 * LINE_HEIGHT + 12.0,
+            }
+        })
+        .collect();
+
+    let row_widths: Vec<f64> = rows.iter()
+        .map(|row| row.iter().map(|&v| placed[v].width).sum::<f64>() + NODE_GAP * row.len().saturating_sub(1) as f64)
+        .collect();
+    let width = row_widths.iter().copied().fold(0.0, f64::max) + 2.0 * MARGIN;
+    let mut y = MARGIN;
+    for (row, row_width) in rows.iter().zip(&row_widths) {
+        let height = row.iter().map(|&v| placed[v].height).fold(0.0, f64::max);
+        let mut x = (width - row_width) / 2.0;
+        for &v in row {
+            placed[v].x = x + placed[v].width / 2.0;
+            placed[v].y = y + height / 2.0;
+            x += placed[v].width + NODE_GAP;
+        }
+        y += height + LAYER_GAP;
+    }
+    let height = if rows.is_empty() { 2.0 * MARGIN } else { y - LAYER_GAP + MARGIN };
+
+    // Horizontal extent of the drawing; curved edges may widen it.
+    let (mut left, mut right) = (0.0, width);
+    let mut svg = String::new();
+
+    // One arrowhead marker per edge colour.
+    let colors: Vec<&str> = {
+        let mut c: Vec<&str> = graph.links.iter().map(|l| attr(&l.attributes, "color", "black")).collect();
+        c.sort_unstable();
+        c.dedup();
+        c
+    };
+    svg.push_str("<defs>\n");
+    for (i, color) in colors.iter().enumerate() {
+        svg.push_str(&format!(
+            "<marker id=\"arrow{}\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"8\" markerHeight=\"8\" \
+             orient=\"auto-start-reverse\"><path d=\"M0,0 L10,5 L0,10 z\" fill=\"{}\"/></marker>\n",
+            i, escape_xml(color)));
+    }
+    svg.push_str("</defs>\n");
+
+    for (s, out) in adj.out.iter().enumerate() {
+        for &(t, l) in out {
+            let link = &graph.links[l];
+            let color = attr(&link.attributes, "color", "black");
+            let marker = colors.iter().position(|c| *c == color).unwrap_or(0);
+            let style = format!("fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"{} marker-end=\"url(#arrow{})\"",
+                escape_xml(color), dash(&link.attributes), marker);
+            let (a, b) = (&placed[s], &placed[t]);
+            let (mx, my);
+            if s == t {
+                let (x, top) = (a.x + a.width / 2.0, a.y - a.height / 4.0);
+                let bottom = a.y + a.height / 4.0;
+                svg.push_str(&format!("<path d=\"M{:.1},{:.1} C{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}\" {}/>\n",
+                    x, top, x + 30.0, top - 10.0, x + 30.0, bottom + 10.0, x, bottom, style));
+                mx = x + 24.0;
+                my = a.y;
+            } else if layer[t] == layer[s] + 1 {
+                let (x1, y1) = clip(a, b.x, b.y);
+                let (x2, y2) = clip(b, a.x, a.y);
+                svg.push_str(&format!("<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" {}/>\n",
+                    x1, y1, x2, y2, style));
+                mx = (x1 + x2) / 2.0;
+                my = (y1 + y2) / 2.0;
+            } else {
+                // Edges that skip layers or point back bend outwards, away
+                // from the nodes in between.
+                let (cx, cy) = ((a.x + b.x) / 2.0, (a.y + b.y) / 2.0);
+                let side = if cx >= width / 2.0 { 1.0 } else { -1.0 };
+                let (qx, qy) = (cx + side * (0.4 * (b.y - a.y).abs() + 40.0), cy);
+                left = f64::min(left, (a.x + 2.0 * qx + b.x) / 4.0 - MARGIN);
+                right = f64::max(right, (a.x + 2.0 * qx + b.x) / 4.0 + MARGIN);
+                let (x1, y1) = clip(a, qx, qy);
+                let (x2, y2) = clip(b, qx, qy);
+                svg.push_str(&format!("<path d=\"M{:.1},{:.1} Q{:.1},{:.1} {:.1},{:.1}\" {}/>\n",
+                    x1, y1, qx, qy, x2, y2, style));
+                mx = (x1 + 2.0 * qx + x2) / 4.0;
+                my = (y1 + 2.0 * qy + y2) / 4.0;
+            }
+            let label = link.display_label.clone().or_else(|| link_label(link, None, graph.directed));
+            if let Some(label) = label.map(|l| l.lines.iter().map(LabelLine::text).collect::<Vec<_>>().join(" ")) {
+                svg.push_str(&format!("<text x=\"{:.1}\" y=\"{:.1}\" font-size=\"{}\" fill=\"{}\">{}</text>\n",
+                    mx + 4.0, my, FONT_SIZE - 2.0,
+                    escape_xml(attr(&link.attributes, "fontcolor", color)), escape_xml(&label)));
+            }
+        }
+    }
+
+    for (v, node) in graph.nodes.iter().enumerate() {
+        let p = &placed[v];
+        let color = attr(&node.attributes, "color", "black");
+        let font_color = attr(&node.attributes, "fontcolor", "black");
+        svg.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"6\" fill=\"white\" stroke=\"{}\" stroke-width=\"1.5\"{}/>\n",
+            p.x - p.width / 2.0, p.y - p.height / 2.0, p.width, p.height, escape_xml(color), dash(&node.attributes)));
+        let first = p.y - (lines[v].len() as f64 - 1.0) * LINE_HEIGHT / 2.0;
+        svg.push_str(&format!("<text text-anchor=\"middle\" dominant-baseline=\"central\" fill=\"{}\">", escape_xml(font_color)));
+        for (i, line) in lines[v].iter().enumerate() {
+            let (x, anchor) = match line.justify {
+                Justify::Left => (p.x - p.width / 2.0 + 10.0, "start"),
+                Justify::Right => (p.x + p.width / 2.0 - 10.0, "end"),
+                Justify::Center => (p.x, "middle"),
+            };
+            svg.push_str(&format!("<tspan x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"{}\">{}</tspan>",
+                x, first + i as f64 * LINE_HEIGHT, anchor, line_spans(line)));
+        }
+        svg.push_str("</text>\n");
+    }
+
+    svg.push_str("</svg>\n");
+
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w:.0}\" height=\"{h:.0}\" viewBox=\"{l:.0} 0 {w:.0} {h:.0}\" \
+         font-family=\"sans-serif\" font-size=\"{f}\">\n\
+         <rect x=\"{l:.0}\" width=\"{w:.0}\" height=\"{h:.0}\" fill=\"white\"/>\n{body}",
+        l = left.floor(), w = (right - left).ceil(), h = height, f = FONT_SIZE, body = svg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::parse_graph;
+
+    fn statuses(graph: &GraphData) -> Vec<(String, &str)> {
+        let nodes = graph.nodes.iter().map(|n| (n.id.clone(), n.attributes["diff"].as_str()));
+        let links = graph.links.iter().map(|l| (format!("{}->{}", l.source, l.target), l.attributes["diff"].as_str()));
+        nodes.chain(links).collect()
+    }
+
+    #[test]
+    fn small_graphs_render_in_layers() {
+        let graph = parse_graph("digraph { a -> b [label=calls]; b -> c; a -> c; c [label=\"x < y\", color=blue] }").unwrap();
+        let svg = render_svg(&graph);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect ").count(), 4, "{}", svg);
+        assert_eq!(svg.matches("<line ").count(), 2);
+        // `a -> c` skips a layer and bends around `b`.
+        assert_eq!(svg.matches(" Q").count(), 1);
+        assert!(svg.contains(">calls</text>"));
+        assert!(svg.contains("x &lt; y</tspan>"));
+        assert!(svg.contains("stroke=\"blue\""));
+
+        let y = |id: &str| {
+            let at = svg.find(&format!(">{}</tspan>", id)).unwrap();
+            let start = svg[..at].rfind(" y=\"").unwrap() + 4;
+            svg[start..at].split('"').next().unwrap().parse::<f64>().unwrap()
+        };
+        assert!(y("a") < y("b") && y("b") < y("x &lt; y"));
+    }
+
+    #[test]
+    fn diffs_mark_added_removed_and_unchanged_elements() {
+        let old = parse_graph("digraph { a -> b; b -> c }").unwrap();
+        let new = parse_graph("digraph { a -> b; a -> d }").unwrap();
+        let diff = diff_graph(&old, &new);
+        assert_eq!(statuses(&diff), vec![
+            ("a".to_string(), "unchanged"), ("b".to_string(), "unchanged"), ("c".to_string(), "removed"), ("d".to_string(), "added"),
+            ("a->b".to_string(), "unchanged"), ("a->d".to_string(), "added"), ("b->c".to_string(), "removed"),
+        ]);
+        let c = &diff.nodes[2].attributes;
+        assert_eq!((c["color"].as_str(), c["style"].as_str()), ("red", "dashed"));
+        assert_eq!(diff.nodes[3].attributes["color"], "green");
+        assert_eq!(diff.links[0].attributes["color"], "grey");
+
+        let dot = diff_dot(&old, &new);
+        assert!(dot.contains("a -> d [color=green, diff=added, fontcolor=green];"), "{}", dot);
+        let svg = render_svg(&diff);
+        assert!(svg.contains("stroke=\"red\" stroke-width=\"1.5\" stroke-dasharray=\"6,4\""));
+        assert!(svg.contains("marker-end=\"url(#arrow1)\"") && svg.contains("fill=\"green\"/></marker>"));
+    }
+
+    #[test]
+    fn parallel_edges_keep_their_multiplicity() {
+        let old = parse_graph("digraph { a -> b; a -> b; b -> c }").unwrap();
+        let new = parse_graph("digraph { a -> b; a -> b; a -> b; b -> c }").unwrap();
+        let diff = diff_graph(&old, &new);
+        let links: Vec<&str> = diff.links.iter().map(|l| l.attributes["diff"].as_str()).collect();
+        assert_eq!(links, vec!["unchanged", "unchanged", "added", "unchanged"]);
+        let back = diff_graph(&new, &old);
+        let links: Vec<&str> = back.links.iter().map(|l| l.attributes["diff"].as_str()).collect();
+        assert_eq!(links, vec!["unchanged", "unchanged", "removed", "unchanged"]);
+    }
+}
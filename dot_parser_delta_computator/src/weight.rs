@@ -0,0 +1,42 @@
+//! Typed edge weights, read from numeric DOT attributes such as `weight`,
+//! `penwidth` or `len`.
+
+use crate::graph::{GraphData, GraphLink};
+use serde::{Serialize, Deserialize};
+
+/// Attributes `parse_graph` reads weights from, highest priority first.
+pub const DEFAULT_WEIGHT_ATTRIBUTES: &[&str] = &["weight", "penwidth", "len"];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WeightOptions {
+    /// Attributes to take the weight from, highest priority first; custom
+    /// attribute names are allowed.
+    pub attributes: Vec<String>,
+}
+
+impl Default for WeightOptions {
+    fn default() -> Self {
+        WeightOptions { attributes: DEFAULT_WEIGHT_ATTRIBUTES.iter().map(|a| a.to_string()).collect() }
+    }
+}
+
+/// A weight value: a finite, non-negative number.
+pub fn parse_weight(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().filter(|w| w.is_finite() && *w >= 0.0)
+}
+
+/// Weight of `link` from the first attribute in `options` holding a valid
+/// number; invalid values are skipped (and reported by `lint_dot`).
+pub fn link_weight(link: &GraphLink, options: &WeightOptions) -> Option<f64> {
+    options.attributes.iter()
+        .filter_map(|a| link.attributes.get(a))
+        .find_map(|v| parse_weight(v))
+}
+
+/// Set `weight` on every link of `graph`. `parse_graph` does this with the
+/// default options; call it again to use another attribute priority.
+pub fn assign_weights(graph: &mut GraphData, options: &WeightOptions) {
+    for link in &mut graph.links {
+        link.weight = link_weight(link, options);
+    }
+}
@@ -9,7 +9,7 @@ pub struct App<'a> {
 }
 
 impl<'a> App<'a> {
-    pub async fn new(window: &'a Window) -> Result<Self> {
+    pub async fn new(window: &'a Window, graph: Graph) -> Result<Self> {
         println!("=== App::new() called ===");
         let size = window.inner_size();
         let aspect = size.width as f32 / size.height as f32;
@@ -19,8 +19,6 @@ impl<'a> App<'a> {
             .await
             .map_err(|e| anyhow::anyhow!("Failed to create GPU state: {}", e))?;
         
-        // Create demo graph and camera
-        let graph = Graph::new_demo();
         let camera = Camera::default_with_aspect(aspect);
 
         // Upload graph data to GPU (sync_graph is synchronous)
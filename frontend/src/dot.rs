@@ -1,15 +1,124 @@
-//! DOT parsing stub — front-end only. Replace with a real parser or pass JSON via JS.
-use crate::graph::Graph;
+//! Loading DOT files into a `Graph`, using the `dot_parser_delta_computator` library
+//! for parsing, colors and the force-directed layout.
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
 
+use dot_parser_delta_computator::{fill_color, parse_graph, ForceLayout, GraphData, LayoutOptions};
+use glam::Vec3;
+
+use crate::graph::{Edge, Graph, Node};
+
+/// Color of nodes without a (valid) `fillcolor` or `color` attribute.
+pub const DEFAULT_NODE_COLOR: [f32; 3] = [0.4, 0.4, 0.4];
+
+/// Upper bound on layout iterations; most graphs converge well before.
+const LAYOUT_STEPS: usize = 500;
+
+/// Radius of the sphere the layout is scaled into, to fit the default camera.
+const GRAPH_RADIUS: f32 = 1.5;
 
 #[derive(Debug)]
-pub enum DotError { Parse(String) }
+pub enum DotError {
+    /// The file could not be read.
+    Io { path: PathBuf, source: std::io::Error },
+    /// The DOT source is invalid; the message points at the offending line and column.
+    Parse(String),
+    /// Two nodes share an id.
+    DuplicateNode(String),
+    /// A link refers to a node that is not in the graph.
+    UnknownNode { id: String, source: String, target: String },
+}
+
+impl fmt::Display for DotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DotError::Io { path, source } => write!(f, "cannot read {}: {}", path.display(), source),
+            DotError::Parse(message) => write!(f, "invalid DOT: {}", message),
+            DotError::DuplicateNode(id) => write!(f, "node {} is declared twice", id),
+            DotError::UnknownNode { id, source, target } => {
+                write!(f, "edge {} -> {} refers to unknown node {}", source, target, id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DotError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Read and parse a DOT file.
+pub fn load_dot(path: &Path) -> Result<Graph, DotError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|source| DotError::Io { path: path.to_path_buf(), source })?;
+    parse_dot(&text)
+}
+
+pub fn parse_dot(text: &str) -> Result<Graph, DotError> {
+    let data = parse_graph(text).map_err(DotError::Parse)?;
+    graph_from_data(&data)
+}
+
+/// Build a `Graph` from parsed graph data: nodes keep the order of `data.nodes`,
+/// are placed by a seeded force layout and colored from their DOT colors.
+pub fn graph_from_data(data: &GraphData) -> Result<Graph, DotError> {
+    let mut index: HashMap<&str, usize> = HashMap::with_capacity(data.nodes.len());
+    for (i, node) in data.nodes.iter().enumerate() {
+        if index.insert(node.id.as_str(), i).is_some() {
+            return Err(DotError::DuplicateNode(node.id.clone()));
+        }
+    }
+
+    let edges = data
+        .links
+        .iter()
+        .map(|link| {
+            let lookup = |id: &str| {
+                index.get(id).copied().ok_or_else(|| DotError::UnknownNode {
+                    id: id.to_string(),
+                    source: link.source.clone(),
+                    target: link.target.clone(),
+                })
+            };
+            Ok(Edge { from: lookup(&link.source)?, to: lookup(&link.target)? })
+        })
+        .collect::<Result<Vec<_>, DotError>>()?;
+
+    let mut layout = ForceLayout::new(data, LayoutOptions::default());
+    layout.run_until_converged(LAYOUT_STEPS);
+    let positions = fit_to_radius(layout.positions().into_iter().map(Vec3::from).collect());
+
+    let nodes = data
+        .nodes
+        .iter()
+        .zip(positions)
+        .map(|(node, position)| {
+            let color = fill_color(&node.colors).map_or(DEFAULT_NODE_COLOR, |c| {
+                let [r, g, b, _] = c.to_f32();
+                [r, g, b]
+            });
+            Node { position, color }
+        })
+        .collect();
 
+    Ok(Graph { nodes, edges })
+}
 
-pub fn parse_dot(_text: &str) -> Result<Graph, DotError> {
-// For the browser-only front-end, you can fetch DOT text via JS/HTTP,
-// parse here (custom or via an external crate compiled to Wasm), then
-// produce a Graph.
-// TODO: integrate a proper DOT parser or use a JSON bridge for speed.
-Err(DotError::Parse("Not implemented".into()))
-}
\ No newline at end of file
+/// Center the positions on the origin and scale them into `GRAPH_RADIUS`.
+fn fit_to_radius(mut positions: Vec<Vec3>) -> Vec<Vec3> {
+    if positions.is_empty() {
+        return positions;
+    }
+    let center = positions.iter().copied().sum::<Vec3>() / positions.len() as f32;
+    let extent = positions.iter().map(|p| p.distance(center)).fold(0.0, f32::max);
+    let scale = if extent > 0.0 { GRAPH_RADIUS / extent } else { 1.0 };
+    for p in &mut positions {
+        *p = (*p - center) * scale;
+    }
+    positions
+}
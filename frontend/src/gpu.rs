@@ -2,6 +2,7 @@ use wgpu::util::DeviceExt;
 use winit::window::Window;
 use bytemuck::{Pod, Zeroable};
 use std::fs;
+use glam::Vec3;
 
 use crate::{camera::Camera, graph::Graph};
 
@@ -371,8 +372,8 @@ impl<'a> GpuState<'a> {
         Ok(())
     }
 
-    /// === NEW: Geometry Generators ===
-    pub fn generate_sphere_vertices(_graph: &Graph) -> (Vec<Vertex>, Vec<u32>) {
+    /// Unit sphere mesh (radius 0.05) centered on the origin, drawn once per node.
+    fn sphere_mesh() -> (Vec<Vertex>, Vec<u32>) {
         let latitude_bands = 12;
         let longitude_bands = 12;
         let radius = 0.05;
@@ -412,154 +413,49 @@ impl<'a> GpuState<'a> {
         (vertices, indices)
     }
 
-    pub fn generate_cylinder_vertices(_graph: &Graph) -> (Vec<Vertex>, Vec<u32>) {
-        use rand::Rng;
-        let mut rng = rand::thread_rng();
-
-        let mut vertices = Vec::new();
-        let mut indices = Vec::new();
-
-        // Make three separate clusters of 20 nodes each, with random edges
-        let clusters = [
-            glam::Vec3::new(-3.0, 0.0, 0.0),
-            glam::Vec3::new(0.0, 0.0, 0.0),
-            glam::Vec3::new(3.0, 0.0, 0.0),
-        ];
-
-        let mut offset = 0u32;
-
-        for &center in &clusters {
-            let mut positions = Vec::new();
-
-            // Create 20 random node positions per cluster
-            for _ in 0..20 {
-                positions.push(center + glam::Vec3::new(
-                    rng.gen_range(-1.0..1.0),
-                    rng.gen_range(-1.0..1.0),
-                    rng.gen_range(-1.0..1.0),
-                ));
+    /// Synchronize the graph data (nodes and edges) to the GPU buffers: one sphere
+    /// per node at its position and in its color, one line per edge.
+    pub fn sync_graph(&mut self, graph: &Graph) {
+        let (sphere_vertices, sphere_indices) = Self::sphere_mesh();
+
+        let mut all_node_vertices = Vec::with_capacity(graph.nodes.len() * sphere_vertices.len());
+        let mut all_node_indices = Vec::with_capacity(graph.nodes.len() * sphere_indices.len());
+        let mut all_edge_vertices = Vec::with_capacity(graph.edges.len() * 2);
+        let mut all_edge_indices = Vec::with_capacity(graph.edges.len() * 2);
+
+        // Nodes (spheres)
+        for node in &graph.nodes {
+            let base_index = all_node_vertices.len() as u32;
+            let [r, g, b] = node.color;
+            for v in &sphere_vertices {
+                all_node_vertices.push(Vertex {
+                    position: (Vec3::from(v.position) + node.position).to_array(),
+                    color: [r, g, b, 1.0],
+                });
             }
-
-            // Add edges between random nodes in this cluster
-            for _ in 0..30 {
-                let a = rng.gen_range(0..20);
-                let b = rng.gen_range(0..20);
-                if a != b {
-                    let pa = positions[a];
-                    let pb = positions[b];
-
-                    let start_index = vertices.len() as u32;
-
-                    vertices.push(Vertex {
-                        position: pa.to_array(),
-                        color: [0.7, 0.7, 0.7, 1.0],
-                    });
-                    vertices.push(Vertex {
-                        position: pb.to_array(),
-                        color: [0.7, 0.7, 0.7, 1.0],
-                    });
-
-                    // Two vertices form one line segment
-                    indices.push(start_index);
-                    indices.push(start_index + 1);
-                }
+            for &i in &sphere_indices {
+                all_node_indices.push(base_index + i);
             }
-
-            offset += 20;
         }
 
-        (vertices, indices)
-    }
-
-}
-
-use rand::Rng;
-use glam::Vec3;
-
-
-impl<'a> GpuState<'a> {
-    /// Synchronize the graph data (nodes and edges) to the GPU buffers.
-    pub fn sync_graph(&mut self, _graph: &Graph) {
-        let mut rng = rand::thread_rng();
-
-        let num_graphs = 7;
-        let nodes_per_graph = 100;
-        let edges_per_graph = 150; // adjust as needed
-
-        let (sphere_vertices, sphere_indices) = Self::generate_sphere_vertices(_graph);
-        let sphere_index_count = sphere_indices.len() as u32;
-
-        let mut all_node_vertices = Vec::new();
-        let mut all_node_indices = Vec::new();
-        let mut all_edge_vertices = Vec::new();
-        let mut all_edge_indices = Vec::new();
-
-        for graph_i in 0..num_graphs {
-            // Random offset for the graph in 3D space
-            let offset = Vec3::new(
-                rng.gen_range(-20.0..20.0),
-                rng.gen_range(-5.0..5.0),
-                rng.gen_range(-20.0..20.0),
-            );
-
-            // Generate random node positions
-            let mut positions = Vec::new();
-            for _ in 0..nodes_per_graph {
-                let pos = Vec3::new(
-                    rng.gen_range(-2.0..2.0),
-                    rng.gen_range(-2.0..2.0),
-                    rng.gen_range(-2.0..2.0),
-                ) + offset;
-                positions.push(pos);
-            }
-
-            // Add nodes (spheres)
-            for pos in &positions {
-                let base_index = all_node_vertices.len() as u32;
-                for v in &sphere_vertices {
-                    let translated = Vertex {
-                        position: [
-                            v.position[0] + pos.x,
-                            v.position[1] + pos.y,
-                            v.position[2] + pos.z,
-                        ],
-                        color: [
-                            0.2 + rng.gen_range(0.0..0.6),
-                            0.4 + rng.gen_range(0.0..0.5),
-                            0.5 + rng.gen_range(0.0..0.5),
-                            1.0,
-                        ],
-                    };
-                    all_node_vertices.push(translated);
-                }
-                for &i in &sphere_indices {
-                    all_node_indices.push(base_index + i);
-                }
-            }
-
-            // Random edges between nodes
-            for _ in 0..edges_per_graph {
-                let a = rng.gen_range(0..nodes_per_graph);
-                let b = rng.gen_range(0..nodes_per_graph);
-                if a == b {
-                    continue;
-                }
-
-                let p1 = positions[a];
-                let p2 = positions[b];
-                let color = [0.8, 0.8, 0.8, 0.3];
-
-                let base_index = all_edge_vertices.len() as u32;
-                all_edge_vertices.push(Vertex {
-                    position: [p1.x, p1.y, p1.z],
-                    color,
-                });
-                all_edge_vertices.push(Vertex {
-                    position: [p2.x, p2.y, p2.z],
-                    color,
-                });
-                all_edge_indices.extend_from_slice(&[base_index, base_index + 1]);
-            }
+        // Edges (lines); edges to missing nodes are skipped
+        let color = [0.8, 0.8, 0.8, 0.3];
+        for edge in &graph.edges {
+            let (Some(from), Some(to)) = (graph.nodes.get(edge.from), graph.nodes.get(edge.to)) else {
+                log::warn!("Skipping edge {} -> {}: node index out of range", edge.from, edge.to);
+                continue;
+            };
+
+            let base_index = all_edge_vertices.len() as u32;
+            all_edge_vertices.push(Vertex {
+                position: from.position.to_array(),
+                color,
+            });
+            all_edge_vertices.push(Vertex {
+                position: to.position.to_array(),
+                color,
+            });
+            all_edge_indices.extend_from_slice(&[base_index, base_index + 1]);
         }
 
         // === Upload node buffers ===
@@ -589,7 +485,6 @@ impl<'a> GpuState<'a> {
         self.edge_num_indices = all_edge_indices.len() as u32;
     }
 
-
     /// Resize the GPU surface and related textures.
     pub fn resize(&mut self, new_width: u32, new_height: u32) {
         if new_width == 0 || new_height == 0 {
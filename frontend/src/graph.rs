@@ -79,4 +79,51 @@ impl Graph {
 
         Self { nodes, edges }
     }
+
+    /// Demo scene: `scenes` random graphs scattered through space, each with
+    /// `nodes_per_scene` randomly colored nodes and up to `edges_per_scene` edges.
+    pub fn random_scenes(scenes: usize, nodes_per_scene: usize, edges_per_scene: usize) -> Self {
+        let mut rng = rand::thread_rng();
+        let mut nodes = Vec::with_capacity(scenes * nodes_per_scene);
+        let mut edges = Vec::new();
+
+        for _ in 0..scenes {
+            // Random offset for the graph in 3D space
+            let offset = Vec3::new(
+                rng.gen_range(-20.0..20.0),
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-20.0..20.0),
+            );
+            let first = nodes.len();
+
+            for _ in 0..nodes_per_scene {
+                nodes.push(Node {
+                    position: offset
+                        + Vec3::new(
+                            rng.gen_range(-2.0..2.0),
+                            rng.gen_range(-2.0..2.0),
+                            rng.gen_range(-2.0..2.0),
+                        ),
+                    color: [
+                        0.2 + rng.gen_range(0.0..0.6),
+                        0.4 + rng.gen_range(0.0..0.5),
+                        0.5 + rng.gen_range(0.0..0.5),
+                    ],
+                });
+            }
+
+            if nodes_per_scene < 2 {
+                continue;
+            }
+            for _ in 0..edges_per_scene {
+                let a = rng.gen_range(0..nodes_per_scene);
+                let b = rng.gen_range(0..nodes_per_scene);
+                if a != b {
+                    edges.push(Edge { from: first + a, to: first + b });
+                }
+            }
+        }
+
+        Self { nodes, edges }
+    }
 }
\ No newline at end of file
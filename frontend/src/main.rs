@@ -8,21 +8,34 @@ use pollster::block_on;
 mod app;
 mod graph;
 mod camera;
+mod dot;
 mod gpu;
 mod input;
 
 use app::App;
+use graph::Graph;
 use input::InputState;
 fn main() {
     env_logger::init();
 
+    // `graph-frontend <file.dot>` shows a file, `--scenes` random demo scenes;
+    // without arguments, the demo graph.
+    let graph = match std::env::args_os().nth(1) {
+        Some(arg) if arg == "--scenes" => Graph::random_scenes(7, 100, 150),
+        Some(path) => dot::load_dot(path.as_ref()).unwrap_or_else(|e| {
+            eprintln!("Failed to load graph: {}", e);
+            std::process::exit(1);
+        }),
+        None => Graph::new_demo(),
+    };
+
     let event_loop = EventLoop::new().unwrap();
     let window = WindowBuilder::new()
         .with_title("3D Graph Visualizer")
         .build(&event_loop)
         .unwrap();
 
-    let mut app = block_on(App::new(&window)).unwrap_or_else(|e| {
+    let mut app = block_on(App::new(&window, graph)).unwrap_or_else(|e| {
         eprintln!("Failed to create App: {:?}", e);
         std::process::exit(1);
     });
@@ -1,10 +1,11 @@
 import * as THREE from 'three';
 import ForceGraph3D from '3d-force-graph';
-import { read } from 'graphlib-dot';
+import init, { DotParser } from './dot_parser_delta_computator/pkg/dot_parser_delta_computator.js';
 import { PointerLockControls } from 'three/examples/jsm/controls/PointerLockControls.js';
 
 let Graph;
-let lastDotText = '';
+let dotParser;
+let lastFingerprint = '';
 let instancedMesh;
 let colorAttr;
 let dummy = new THREE.Object3D();
@@ -25,214 +26,74 @@ let cullingUpdateInterval = 100;
 let lastCullingUpdate = 0;
 
 // ============================================
-// ENHANCED LOUVAIN-INSPIRED CLUSTERING
+// CLUSTERING (Louvain, computed in Rust/WASM)
 // ============================================
-function clusterNodes(nodes, links) {
-    console.log("🔍 Starting advanced clustering...");
-    
-    // Build weighted adjacency list
-    const adj = new Map();
-    const nodeDegree = new Map();
-    
-    nodes.forEach(node => {
-        adj.set(node.id, []);
-        nodeDegree.set(node.id, 0);
-    });
-    
-    links.forEach(link => {
-        const sourceId = typeof link.source === 'object' ? link.source.id : link.source;
-        const targetId = typeof link.target === 'object' ? link.target.id : link.target;
-        const weight = link.weight || 0.5;
-        
-        if (adj.has(sourceId) && adj.has(targetId)) {
-            adj.get(sourceId).push({ node: targetId, weight });
-            adj.get(targetId).push({ node: sourceId, weight });
-            nodeDegree.set(sourceId, nodeDegree.get(sourceId) + weight);
-            nodeDegree.set(targetId, nodeDegree.get(targetId) + weight);
-        }
-    });
+const CLUSTER_SEED = 42;
+const MIN_CLUSTER_SIZE = 3;
 
-    // Initialize communities (each node starts in its own)
-    const communities = new Map();
-    nodes.forEach((node, i) => {
-        communities.set(node.id, i);
-        node.cluster = i;
-    });
-    
-    // Phase 1: Modularity optimization (Louvain-like)
-    let improved = true;
-    let iteration = 0;
-    const maxIterations = 15;
-    
-    while (improved && iteration < maxIterations) {
-        improved = false;
-        iteration++;
-        
-        // Shuffle for randomness
-        const shuffledNodes = [...nodes].sort(() => Math.random() - 0.5);
-        
-        for (const node of shuffledNodes) {
-            const currentComm = communities.get(node.id);
-            const neighbors = adj.get(node.id) || [];
-            
-            // Calculate weighted connections to each neighboring community
-            const commWeights = new Map();
-            
-            neighbors.forEach(({ node: neighborId, weight }) => {
-                const neighborComm = communities.get(neighborId);
-                if (neighborComm !== undefined) {
-                    commWeights.set(neighborComm, (commWeights.get(neighborComm) || 0) + weight);
-                }
-            });
-            
-            // Find best community (highest total weight)
-            let bestComm = currentComm;
-            let bestWeight = commWeights.get(currentComm) || 0;
-            
-            for (const [comm, weight] of commWeights) {
-                // Only move if significantly better (to avoid oscillation)
-                if (weight > bestWeight * 1.1) {
-                    bestWeight = weight;
-                    bestComm = comm;
-                }
-            }
-            
-            if (bestComm !== currentComm) {
-                communities.set(node.id, bestComm);
-                node.cluster = bestComm;
-                improved = true;
-            }
-        }
-        
-        console.log(`  Iteration ${iteration}: ${new Set(communities.values()).size} communities`);
-    }
-    
-    // Phase 2: Merge small clusters into nearest neighbors
-    const clusterGroups = new Map();
+// Group nodes by the `cluster` id assigned by DotParser.cluster()
+function groupClusters(nodes) {
+    const groups = new Map();
     nodes.forEach(node => {
-        const clusterId = communities.get(node.id);
-        if (!clusterGroups.has(clusterId)) {
-            clusterGroups.set(clusterId, []);
-        }
-        clusterGroups.get(clusterId).push(node);
-    });
-    
-    const minClusterSize = 3; // Merge clusters smaller than this
-    const smallClusters = [];
-    
-    clusterGroups.forEach((nodeList, clusterId) => {
-        if (nodeList.length < minClusterSize) {
-            smallClusters.push({ id: clusterId, nodes: nodeList });
+        if (!groups.has(node.cluster)) {
+            groups.set(node.cluster, []);
         }
+        groups.get(node.cluster).push(node);
     });
-    
-    // Merge small clusters
-    smallClusters.forEach(smallCluster => {
-        let bestTarget = null;
-        let maxConnection = -1;
-        
-        // Find the cluster with strongest connection
-        clusterGroups.forEach((targetNodes, targetId) => {
-            if (targetId === smallCluster.id || targetNodes.length < minClusterSize) return;
-            
-            let connectionWeight = 0;
-            smallCluster.nodes.forEach(node => {
-                const neighbors = adj.get(node.id) || [];
-                neighbors.forEach(({ node: neighborId, weight }) => {
-                    const neighborNode = nodes.find(n => n.id === neighborId);
-                    if (neighborNode && communities.get(neighborNode.id) === targetId) {
-                        connectionWeight += weight;
-                    }
-                });
-            });
-            
-            if (connectionWeight > maxConnection) {
-                maxConnection = connectionWeight;
-                bestTarget = targetId;
-            }
-        });
-        
-        // Merge into best target
-        if (bestTarget !== null) {
-            smallCluster.nodes.forEach(node => {
-                communities.set(node.id, bestTarget);
-                node.cluster = bestTarget;
-            });
-            
-            clusterGroups.get(bestTarget).push(...smallCluster.nodes);
-            clusterGroups.delete(smallCluster.id);
-        }
-    });
-    
-    // Phase 3: Renumber clusters sequentially
-    const finalClusterMap = new Map();
-    let clusterId = 0;
-    
-    clusterGroups.forEach((nodeList, oldId) => {
-        finalClusterMap.set(oldId, clusterId++);
-    });
-    
-    nodes.forEach(node => {
-        const oldCluster = communities.get(node.id);
-        node.cluster = finalClusterMap.get(oldCluster);
-    });
-    
-    // Build final cluster array
+
     const clusters = [];
-    const finalGroups = new Map();
-    
-    nodes.forEach(node => {
-        if (!finalGroups.has(node.cluster)) {
-            finalGroups.set(node.cluster, []);
-        }
-        finalGroups.get(node.cluster).push(node);
-    });
-    
-    finalGroups.forEach((nodeList, id) => {
+    groups.forEach((nodeList, id) => {
         clusters.push({
             id,
             nodes: nodeList,
             size: nodeList.length
         });
     });
-    
+
     // Sort by size for better visualization
     clusters.sort((a, b) => b.size - a.size);
-    
+
     console.log(`✅ Created ${clusters.length} clusters`);
     console.log(`📊 Cluster sizes:`, clusters.map(c => c.size).join(', '));
-    
+
     return clusters;
 }
 
+// Fill color resolved by the parser (fillcolor, else color), as CSS.
+function resolvedColor(node) {
+    const colors = node.colors || {};
+    const c = colors.fillcolor || colors.color;
+    return c ? `rgb(${c.r}, ${c.g}, ${c.b})` : '#666666';
+}
+
 // ---------------- convertGraph() ----------------
 function convertGraph(graph) {
-    const nodes = graph.nodes().map((id, i) => {
-        const attrs = graph.node(id) || {};
+    const nodes = graph.nodes.map((node, i) => {
+        const attrs = node.attributes || {};
         return {
-            id,
-            label: attrs.label || id,
-            color: attrs.fillcolor || attrs.color || '#666666',
+            id: node.id,
+            label: attrs.label || node.id,
             x: (Math.random() - 0.5) * 100,
             y: (Math.random() - 0.5) * 100,
             z: (Math.random() - 0.5) * 100,
             index: i,
-            ...attrs
+            ...attrs,
+            color: resolvedColor(node),
+            cluster: node.cluster
         };
     });
 
-    const links = graph.edges().map(edge => {
-        const edgeAttrs = graph.edge(edge) || {};
+    const links = graph.links.map(link => {
+        const edgeAttrs = link.attributes || {};
         return {
-            source: edge.v,
-            target: edge.w,
-            weight: parseFloat(edgeAttrs.label) || 0.5,
+            source: link.source,
+            target: link.target,
+            weight: link.weight ?? 0.5,
             penwidth: parseFloat(edgeAttrs.penwidth) || 1
         };
     });
 
-    // Perform clustering
-    const clusters = clusterNodes(nodes, links);
+    const clusters = groupClusters(nodes);
     
     // Assign cluster-based positions
     assignClusterPositions(nodes, clusters);
@@ -708,9 +569,15 @@ async function pollDotFiles(url, interval = 60000) {
         const res = await fetch(url, { cache: "no-store" });
         const newDotText = await res.text();
 
-        if (newDotText !== lastDotText) {
-            lastDotText = newDotText;
-            const graph = read(newDotText);
+        if (!dotParser) {
+            await init();
+            dotParser = new DotParser();
+        }
+        // Skip re-rendering when only formatting or statement order changed.
+        const fingerprint = dotParser.fingerprint(newDotText);
+        if (fingerprint !== lastFingerprint) {
+            lastFingerprint = fingerprint;
+            const graph = dotParser.cluster(newDotText, BigInt(CLUSTER_SEED), MIN_CLUSTER_SIZE);
             const { nodes, links } = convertGraph(graph);
             updateGraph({ nodes, links });
         }